@@ -0,0 +1,209 @@
+use crate::*;
+use crate::pgn::{PgnError, PgnGame};
+use crate::search::{is_mate_score, SearchLimits, Searcher};
+
+// mate scores count as this much when moves are compared
+const MATE_CAP: i32 = 1000;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MoveClass {
+    // the engine's own choice
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl MoveClass {
+    // by centipawn loss, the usual 50/100/300 limits
+    pub fn from_loss(cp_loss: i32) -> MoveClass {
+        match cp_loss {
+            loss if loss >= 300 => MoveClass::Blunder,
+            loss if loss >= 100 => MoveClass::Mistake,
+            loss if loss >= 50 => MoveClass::Inaccuracy,
+            _ => MoveClass::Good,
+        }
+    }
+
+    // ?! ? and ?? as numeric annotation glyphs
+    pub fn nag(&self) -> Option<&'static str> {
+        match self {
+            MoveClass::Inaccuracy => Some("$6"),
+            MoveClass::Mistake => Some("$2"),
+            MoveClass::Blunder => Some("$4"),
+            MoveClass::Best | MoveClass::Good => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MoveAnalysis {
+    pub mv: GameMove,
+    pub san: String,
+    pub color: Color,
+    // from the mover, before and after the move
+    pub score_before: i32,
+    pub score_after: i32,
+    pub best_move: Option<GameMove>,
+    pub best_san: Option<String>,
+    pub cp_loss: i32,
+    pub accuracy: f64,
+    pub class: MoveClass,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlayerSummary {
+    pub moves: usize,
+    pub average_cp_loss: f64,
+    // 0 to 100, the average of the move accuracies
+    pub accuracy: f64,
+    pub inaccuracies: usize,
+    pub mistakes: usize,
+    pub blunders: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct GameReport {
+    pub moves: Vec<MoveAnalysis>,
+    pub white: PlayerSummary,
+    pub black: PlayerSummary,
+}
+
+fn capped(score: i32) -> i32 {
+    if is_mate_score(score) { score.signum() * MATE_CAP } else { score.clamp(-MATE_CAP, MATE_CAP) }
+}
+
+// the chance to win from a score, 0 to 100
+fn win_percent(score: i32) -> f64 {
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.00368208 * capped(score) as f64).exp()) - 1.0)
+}
+
+// how much of the winning chance the move kept, 100 for the best move
+fn move_accuracy(before: i32, after: i32) -> f64 {
+    let drop = (win_percent(before) - win_percent(after)).max(0.0);
+    (103.1668 * (-0.04354 * drop).exp() - 3.1669).clamp(0.0, 100.0)
+}
+
+fn summarize(moves: &[MoveAnalysis], color: Color) -> PlayerSummary {
+    let own: Vec<&MoveAnalysis> = moves.iter().filter(|analysis| analysis.color == color).collect();
+    if own.is_empty() {
+        return PlayerSummary::default();
+    }
+    let count = |class: MoveClass| own.iter().filter(|analysis| analysis.class == class).count();
+    PlayerSummary {
+        moves: own.len(),
+        average_cp_loss: own.iter().map(|analysis| analysis.cp_loss as f64).sum::<f64>() / own.len() as f64,
+        accuracy: own.iter().map(|analysis| analysis.accuracy).sum::<f64>() / own.len() as f64,
+        inaccuracies: count(MoveClass::Inaccuracy),
+        mistakes: count(MoveClass::Mistake),
+        blunders: count(MoveClass::Blunder),
+    }
+}
+
+/* searches every position of the game once with the same limits, the loss
+of a move is the score before it minus the score after it */
+pub fn analyse_game(pgn_game: &PgnGame, searcher: &mut Searcher, limits: SearchLimits) -> Result<GameReport, PgnError> {
+    let mut positions = vec![pgn_game.start_position()?];
+    for (ply, mv) in pgn_game.moves.iter().enumerate() {
+        let mut next = positions.last().expect("starts with the start position").clone();
+        next.make_move(mv).map_err(|_| PgnError::UnplayableMove { game: None, ply: ply, uci: mv.to_uci() })?;
+        positions.push(next);
+    }
+    searcher.set_limits(limits);
+    let results: Vec<_> = positions.iter().map(|game| searcher.run(game)).collect();
+
+    let mut moves = vec![];
+    for (i, mv) in pgn_game.moves.iter().enumerate() {
+        let game = &positions[i];
+        let before = results[i].score;
+        let after = -results[i + 1].score;
+        let best_move = results[i].best_move;
+        let cp_loss = (capped(before) - capped(after)).max(0);
+        let class = if best_move == Some(*mv) { MoveClass::Best } else { MoveClass::from_loss(cp_loss) };
+        moves.push(MoveAnalysis {
+            mv: *mv,
+            san: game.to_san(mv),
+            color: game.turn,
+            score_before: before,
+            score_after: after,
+            best_move: best_move,
+            best_san: best_move.map(|best| game.to_san(&best)),
+            cp_loss: if class == MoveClass::Best { 0 } else { cp_loss },
+            accuracy: if class == MoveClass::Best { 100.0 } else { move_accuracy(before, after) },
+            class: class,
+        });
+    }
+    Ok(GameReport {
+        white: summarize(&moves, Color::White),
+        black: summarize(&moves, Color::Black),
+        moves: moves,
+    })
+}
+
+impl GameReport {
+    // NAGs on the bad moves with the engine's move in a comment
+    pub fn to_pgn(&self, pgn_game: &PgnGame) -> Result<String, PgnError> {
+        let mut annotated = pgn_game.clone();
+        for (color, summary) in [("White", &self.white), ("Black", &self.black)] {
+            annotated.tags.push((format!("{}Accuracy", color), format!("{:.1}", summary.accuracy)));
+        }
+        annotated.to_pgn_with(|i| {
+            let analysis = match self.moves.get(i) {
+                Some(analysis) => analysis,
+                None => return vec![],
+            };
+            match (analysis.class.nag(), &analysis.best_san) {
+                (Some(nag), Some(best_san)) => vec![
+                    nag.to_string(),
+                    format!("{{{:?} ({}), {} was best}}", analysis.class, analysis.cp_loss, best_san),
+                ],
+                (Some(nag), None) => vec![nag.to_string()],
+                (None, _) => vec![],
+            }
+        })
+    }
+
+    // e.g. "White: 91.2% accuracy, 14 acpl, 1 inaccuracies, 0 mistakes, 0 blunders"
+    pub fn summary(&self) -> String {
+        [("White", &self.white), ("Black", &self.black)].iter()
+            .map(|(color, summary)| format!("{}: {:.1}% accuracy, {:.0} acpl, {} inaccuracies, {} mistakes, {} blunders",
+                color, summary.accuracy, summary.average_cp_loss, summary.inaccuracies, summary.mistakes, summary.blunders))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::parse_pgn;
+
+    #[test]
+    fn classifies_losses() {
+        assert_eq!(MoveClass::from_loss(20), MoveClass::Good);
+        assert_eq!(MoveClass::from_loss(60), MoveClass::Inaccuracy);
+        assert_eq!(MoveClass::from_loss(150), MoveClass::Mistake);
+        assert_eq!(MoveClass::from_loss(MATE_CAP), MoveClass::Blunder);
+        assert_eq!(move_accuracy(30, 30).round(), 100.0);
+        assert!(move_accuracy(0, -500) < 30.0);
+    }
+
+    #[test]
+    fn reports_blunder() {
+        let game = parse_pgn("1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0").unwrap().remove(0);
+        let mut searcher = Searcher::new(SearchLimits::default());
+        let report = analyse_game(&game, &mut searcher, SearchLimits::depth(2)).unwrap();
+        assert_eq!(report.moves.len(), 7);
+        assert_eq!(report.moves[5].class, MoveClass::Blunder);
+        assert_eq!(report.moves[6].class, MoveClass::Best);
+        assert_eq!(report.black.blunders, 1);
+        assert!(report.white.accuracy > report.black.accuracy);
+
+        let text = report.to_pgn(&game).unwrap();
+        assert!(text.contains("Nf6 $4 {Blunder"));
+        assert!(text.contains("4. Qxf7# 1-0"));
+        // the annotated game reads back as the same game
+        assert_eq!(parse_pgn(&text).unwrap()[0].moves, game.moves);
+    }
+}
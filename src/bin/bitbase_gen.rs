@@ -0,0 +1,38 @@
+use std::process;
+use std::time::Instant;
+
+use INDA22PlusPlus_antmag_hw3::bitbase::{Bitbases, Material};
+
+// bitbase_gen <dir> [KPK KRK ...], tables the asked ones depend on are made too
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let dir = match args.first() {
+        Some(dir) => dir.clone(),
+        None => {
+            eprintln!("usage: bitbase_gen <dir> [material...]");
+            process::exit(2);
+        }
+    };
+    let names: Vec<&str> = match args.len() {
+        1 => vec!["KQK", "KRK", "KPK"],
+        _ => args[1..].iter().map(|name| name.as_str()).collect(),
+    };
+
+    let mut bitbases = Bitbases::load_dir(&dir).unwrap_or_default();
+    for name in names {
+        let material = match Material::parse(name) {
+            Ok(material) => material,
+            Err(e) => {
+                eprintln!("{}: {:?}", name, e);
+                process::exit(2);
+            }
+        };
+        let start = Instant::now();
+        bitbases.generate(&material);
+        println!("{} done in {:.1}s", material.name(), start.elapsed().as_secs_f64());
+    }
+    if let Err(e) = bitbases.save_dir(&dir) {
+        eprintln!("could not save to {}: {:?}", dir, e);
+        process::exit(1);
+    }
+}
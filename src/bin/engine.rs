@@ -0,0 +1,21 @@
+use std::io::{self, BufRead, Cursor, Read};
+
+use INDA22PlusPlus_antmag_hw3::{uci, xboard};
+
+// the first command decides the protocol, xboard GUIs always start with "xboard"
+fn main() {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut first_line = String::new();
+    if input.read_line(&mut first_line).is_err() {
+        return;
+    }
+
+    let is_xboard = first_line.trim() == "xboard";
+    let input = Cursor::new(first_line).chain(input);
+    if is_xboard {
+        xboard::run(input, io::stdout());
+    } else {
+        uci::run(input, io::stdout());
+    }
+}
@@ -0,0 +1,44 @@
+use std::fs;
+use std::process;
+use std::time::Duration;
+
+use INDA22PlusPlus_antmag_hw3::epd::{parse_epd, run_suite};
+use INDA22PlusPlus_antmag_hw3::search::{SearchLimits, Searcher};
+
+// epd_suite <file> [movetime in ms], every position gets the same time
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let path = match args.first() {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: epd_suite <file.epd> [movetime ms]");
+            process::exit(2);
+        }
+    };
+    let millis = args.get(1).and_then(|ms| ms.parse().ok()).unwrap_or(1000);
+
+    let text = fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("could not read {}: {}", path, e);
+        process::exit(1);
+    });
+    let positions = parse_epd(&text).unwrap_or_else(|e| {
+        eprintln!("bad EPD: {:?}", e);
+        process::exit(1);
+    });
+
+    let mut searcher = Searcher::new(SearchLimits::default());
+    let limits = SearchLimits::movetime(Duration::from_millis(millis));
+    let report = run_suite(&positions, &mut searcher, limits, |result| {
+        if let Some(error) = &result.error {
+            println!("{:<16} SKIP {:?}", result.id, error);
+            return;
+        }
+        let best_move = result.best_move.map(|mv| mv.to_uci()).unwrap_or_else(|| String::from("none"));
+        println!("{:<16} {:<4} {:<6} depth {:<3} score {}",
+            result.id, if result.passed { "ok" } else { "FAIL" }, best_move, result.depth, result.score);
+    }).unwrap_or_else(|e| {
+        eprintln!("bad EPD: {:?}", e);
+        process::exit(1);
+    });
+    println!("{}", report.summary());
+}
@@ -0,0 +1,39 @@
+use std::fs;
+use std::process;
+
+use INDA22PlusPlus_antmag_hw3::pgn::parse_pgn;
+use INDA22PlusPlus_antmag_hw3::puzzle::save_puzzles;
+use INDA22PlusPlus_antmag_hw3::puzzle_extract::{extract_puzzles, ExtractOptions};
+use INDA22PlusPlus_antmag_hw3::search::{SearchLimits, Searcher};
+
+// extract_puzzles <games.pgn> <puzzles.txt> [depth], the output is read by the puzzle trainer
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() < 2 {
+        eprintln!("usage: extract_puzzles <games.pgn> <puzzles.txt> [depth]");
+        process::exit(2);
+    }
+    let mut options = ExtractOptions::default();
+    if let Some(depth) = args.get(2).and_then(|depth| depth.parse().ok()) {
+        options.limits = SearchLimits::depth(depth);
+    }
+
+    let text = fs::read_to_string(&args[0]).unwrap_or_else(|e| {
+        eprintln!("could not read {}: {}", args[0], e);
+        process::exit(1);
+    });
+    let games = parse_pgn(&text).unwrap_or_else(|e| {
+        eprintln!("bad PGN: {:?}", e);
+        process::exit(1);
+    });
+    let mut searcher = Searcher::new(options.limits);
+    let puzzles = extract_puzzles(&games, &mut searcher, &options, "game").unwrap_or_else(|e| {
+        eprintln!("bad PGN: {:?}", e);
+        process::exit(1);
+    });
+    if let Err(e) = save_puzzles(&args[1], &puzzles) {
+        eprintln!("could not write {}: {:?}", args[1], e);
+        process::exit(1);
+    }
+    println!("{} puzzles from {} games", puzzles.len(), games.len());
+}
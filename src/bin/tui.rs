@@ -0,0 +1,259 @@
+use std::io::{self, Stdout, Write};
+use std::process;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::{self, Color as TermColor, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, execute, queue};
+
+use INDA22PlusPlus_antmag_hw3::*;
+use INDA22PlusPlus_antmag_hw3::Definitions::Color;
+use INDA22PlusPlus_antmag_hw3::fen::START_FEN;
+use INDA22PlusPlus_antmag_hw3::netcode::{Connection, NetMessage};
+use INDA22PlusPlus_antmag_hw3::tui::{SquareMark, TuiAction, TuiKey, TuiState};
+
+// same address as the GUI, so either frontend can host the other
+const SERVER_ADDR: &str = "127.0.0.1:1337";
+
+// one end of a network game, the reader thread hands over what arrives
+struct Network {
+    connection: Connection,
+    incoming: Receiver<io::Result<Option<NetMessage>>>,
+    // the client's move waiting for the server's answer
+    pending: Option<GameMove>,
+}
+
+fn fail(text: &str) -> ! {
+    eprintln!("{}", text);
+    process::exit(1);
+}
+
+/* the server plays black like the GUI server, answers the client's connect
+request and starts from the usual position */
+fn host(addr: &str) -> (Connection, Game) {
+    println!("Waiting for a client on {}", addr);
+    let mut connection = Connection::listen(addr).unwrap_or_else(|e| fail(&format!("could not listen on {}: {}", addr, e)));
+    loop {
+        match connection.receive() {
+            Ok(Some(NetMessage::ConnectRequest)) => break,
+            Ok(_) => continue,
+            Err(e) => fail(&format!("the client did not connect: {}", e)),
+        }
+    }
+    let ack = NetMessage::ConnectAck { success: true, client_is_white: true, fen: Some(String::from(START_FEN)) };
+    connection.send(&ack).unwrap_or_else(|e| fail(&format!("could not answer the client: {}", e)));
+    (connection, create_game())
+}
+
+fn join(addr: &str) -> (Connection, Game, Color) {
+    let mut connection = Connection::connect(addr).unwrap_or_else(|e| fail(&format!("could not connect to {}: {}", addr, e)));
+    connection.send(&NetMessage::ConnectRequest).unwrap_or_else(|e| fail(&format!("could not send the connect request: {}", e)));
+    loop {
+        match connection.receive() {
+            Ok(Some(NetMessage::ConnectAck { success: false, .. })) => fail("the server rejected the connection"),
+            Ok(Some(NetMessage::ConnectAck { client_is_white, fen, .. })) => {
+                let game = match fen {
+                    Some(fen) => Game::from_fen(&fen).unwrap_or_else(|e| fail(&format!("the server sent a bad position: {:?}", e))),
+                    None => create_game(),
+                };
+                return (connection, game, if client_is_white { Color::White } else { Color::Black });
+            },
+            Ok(_) => continue,
+            Err(e) => fail(&format!("the server did not answer: {}", e)),
+        }
+    }
+}
+
+fn start_reader(connection: &Connection) -> Receiver<io::Result<Option<NetMessage>>> {
+    let mut reader = connection.try_clone().unwrap_or_else(|e| fail(&format!("could not read from the socket: {}", e)));
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || loop {
+        let received = reader.receive();
+        let closed = received.is_err();
+        if sender.send(received).is_err() || closed {
+            return;
+        }
+    });
+    receiver
+}
+
+fn square_color(xy: (usize, usize), mark: SquareMark) -> TermColor {
+    match mark {
+        SquareMark::Cursor => TermColor::Rgb { r: 90, g: 140, b: 230 },
+        SquareMark::Selected => TermColor::Rgb { r: 230, g: 200, b: 80 },
+        SquareMark::Destination => TermColor::Rgb { r: 120, g: 190, b: 110 },
+        SquareMark::LastMove => TermColor::Rgb { r: 200, g: 170, b: 120 },
+        SquareMark::Plain if (xy.0 + xy.1) % 2 == 0 => TermColor::Rgb { r: 235, g: 220, b: 190 },
+        SquareMark::Plain => TermColor::Rgb { r: 170, g: 130, b: 95 },
+    }
+}
+
+fn draw(out: &mut Stdout, state: &TuiState) -> io::Result<()> {
+    queue!(out, terminal::Clear(ClearType::All))?;
+    let order: Vec<usize> = if state.is_flipped() { (0..8).rev().collect() } else { (0..8).collect() };
+
+    for (row, &y) in order.iter().enumerate() {
+        queue!(out, cursor::MoveTo(0, row as u16), Print(format!("{} ", 8 - y)))?;
+        for &x in order.iter() {
+            let symbol = match state.game().get_content((x, y)) {
+                Content::Empty => " ",
+                content => content.get_symbol(),
+            };
+            queue!(out,
+                SetBackgroundColor(square_color((x, y), state.mark((x, y)))),
+                SetForegroundColor(TermColor::Black),
+                Print(format!(" {} ", symbol)),
+                ResetColor)?;
+        }
+    }
+    let files: String = order.iter().map(|&x| format!(" {} ", (b'a' + x as u8) as char)).collect();
+    queue!(out, cursor::MoveTo(0, 8), Print(format!("  {}", files)))?;
+
+    // the last moves next to the board
+    let moves = state.move_list();
+    for (row, line) in moves.iter().skip(moves.len().saturating_sub(8)).enumerate() {
+        queue!(out, cursor::MoveTo(30, row as u16), Print(line))?;
+    }
+
+    queue!(out,
+        cursor::MoveTo(0, 10), Print(state.status()),
+        cursor::MoveTo(0, 11), style::SetAttribute(style::Attribute::Dim),
+        Print("arrows + Enter to move, or type a move (e4, Nf3, e7e8q) and Enter, Esc quits"),
+        style::SetAttribute(style::Attribute::Reset),
+        cursor::MoveTo(0, 12), Print(format!("> {}", state.input())))?;
+    out.flush()
+}
+
+fn read_key() -> io::Result<Option<TuiKey>> {
+    if !event::poll(Duration::from_millis(50))? {
+        return Ok(None);
+    }
+    let key = match event::read()? {
+        Event::Key(key) if key.kind == KeyEventKind::Press => key,
+        _ => return Ok(None),
+    };
+    Ok(match key.code {
+        KeyCode::Up => Some(TuiKey::Up),
+        KeyCode::Down => Some(TuiKey::Down),
+        KeyCode::Left => Some(TuiKey::Left),
+        KeyCode::Right => Some(TuiKey::Right),
+        KeyCode::Enter => Some(TuiKey::Enter),
+        KeyCode::Esc => Some(TuiKey::Escape),
+        KeyCode::Backspace => Some(TuiKey::Backspace),
+        KeyCode::Char(c) => Some(TuiKey::Char(c)),
+        _ => None,
+    })
+}
+
+// a move picked in this terminal, over the network the server decides for the client
+fn play_move(state: &mut TuiState, network: &mut Option<Network>, mv: GameMove) -> io::Result<()> {
+    match network {
+        Some(network) if network.connection.role() == netcode::NetRole::Client => {
+            network.pending = Some(mv);
+            network.connection.send(&NetMessage::Move(mv))?;
+            state.set_status("Waiting for the server");
+        },
+        Some(network) => {
+            state.apply_move(&mv).map_err(|e| io::Error::other(format!("{:?}", e)))?;
+            network.connection.send(&NetMessage::Move(mv))?;
+        },
+        None => {
+            if let Err(e) = state.apply_move(&mv) {
+                state.set_status(&format!("Could not make the move: {:?}", e));
+            }
+        },
+    }
+    Ok(())
+}
+
+fn on_message(state: &mut TuiState, network: &mut Network, msg: NetMessage) -> io::Result<()> {
+    match msg {
+        NetMessage::Move(mv) => {
+            let legal = state.apply_move(&mv).is_ok();
+            if network.connection.role() == netcode::NetRole::Server {
+                network.connection.send(&NetMessage::MoveAck(legal))?;
+            }
+            if !legal {
+                state.set_status(&format!("The opponent sent an illegal move: {}", mv.to_uci()));
+            }
+        },
+        NetMessage::MoveAck(legal) => {
+            match network.pending.take() {
+                Some(mv) if legal => {
+                    if let Err(e) = state.apply_move(&mv) {
+                        state.set_status(&format!("The server accepted a move we can not make: {:?}", e));
+                    }
+                },
+                _ => state.set_status("The server did not accept the move"),
+            }
+        },
+        _ => (),
+    }
+    Ok(())
+}
+
+fn run(out: &mut Stdout, state: &mut TuiState, network: &mut Option<Network>) -> io::Result<()> {
+    loop {
+        draw(out, state)?;
+        let received = network.as_ref().and_then(|network| network.incoming.try_recv().ok());
+        match received {
+            Some(Ok(Some(msg))) => on_message(state, network.as_mut().expect("messages come from the network"), msg)?,
+            Some(Ok(None)) => (),
+            Some(Err(e)) => {
+                state.set_status(&format!("Connection lost: {}", e));
+                *network = None;
+            },
+            None => (),
+        }
+
+        let key = match read_key()? {
+            Some(key) => key,
+            None => continue,
+        };
+        let waiting = network.as_ref().is_some_and(|network| network.pending.is_some());
+        match state.handle_key(key) {
+            TuiAction::Quit => return Ok(()),
+            TuiAction::Move(_) if waiting => state.set_status("Waiting for the server"),
+            TuiAction::Move(mv) => play_move(state, network, mv)?,
+            TuiAction::None => (),
+        }
+    }
+}
+
+// tui, tui --server [addr] or tui --client <addr>
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (game, player, connection) = match args.first().map(|arg| arg.as_str()) {
+        None => (create_game(), None, None),
+        Some("--server") => {
+            let (connection, game) = host(args.get(1).map(|addr| addr.as_str()).unwrap_or(SERVER_ADDR));
+            (game, Some(Color::Black), Some(connection))
+        },
+        Some("--client") => {
+            let addr = args.get(1).unwrap_or_else(|| fail("usage: tui --client <ip:port>"));
+            let (connection, game, color) = join(addr);
+            (game, Some(color), Some(connection))
+        },
+        Some(_) => fail("usage: tui [--server [ip:port] | --client <ip:port>]"),
+    };
+    let mut network = connection.map(|connection| Network {
+        incoming: start_reader(&connection),
+        connection: connection,
+        pending: None,
+    });
+    let mut state = TuiState::new(game, player);
+
+    let mut out = io::stdout();
+    terminal::enable_raw_mode().unwrap_or_else(|e| fail(&format!("not a terminal: {}", e)));
+    execute!(out, terminal::EnterAlternateScreen, cursor::Hide).ok();
+    let result = run(&mut out, &mut state, &mut network);
+    // the terminal is given back even when the game failed
+    execute!(out, cursor::Show, terminal::LeaveAlternateScreen).ok();
+    terminal::disable_raw_mode().ok();
+    if let Err(e) = result {
+        fail(&format!("{}", e));
+    }
+}
@@ -0,0 +1,668 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::*;
+
+/* one byte per position, from the side to move:
+0 draw, 255 not a legal position, otherwise plies to mate + 1.
+An odd number of plies is a win, an even number a loss (0 is mated) */
+const DRAW: u8 = 0;
+const ILLEGAL: u8 = 255;
+// only used while generating
+const UNKNOWN: u8 = 254;
+pub(crate) const MAX_PLIES: u32 = 252;
+
+const MAGIC: &[u8; 4] = b"ABB1";
+const FILE_EXTENSION: &str = "abb";
+// more pieces make tables too big to generate with the game's move generator
+const MAX_PIECES: usize = 2;
+
+#[derive(Debug)]
+pub enum BitbaseError {
+    Io(io::Error),
+    InvalidMaterial(String),
+    BadFile,
+}
+
+impl From<io::Error> for BitbaseError {
+    fn from(e: io::Error) -> BitbaseError {
+        BitbaseError::Io(e)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Wdl {
+    Win,
+    Draw,
+    Loss,
+}
+
+// the result for the side to move
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Probe {
+    pub wdl: Wdl,
+    // None for draws
+    pub plies_to_mate: Option<u32>,
+}
+
+fn probe_from_code(code: u8) -> Option<Probe> {
+    match code {
+        ILLEGAL | UNKNOWN => None,
+        DRAW => Some(Probe { wdl: Wdl::Draw, plies_to_mate: None }),
+        _ => {
+            let plies = code as u32 - 1;
+            let wdl = if plies % 2 == 1 { Wdl::Win } else { Wdl::Loss };
+            Some(Probe { wdl: wdl, plies_to_mate: Some(plies) })
+        }
+    }
+}
+
+fn type_order(piece_type: PieceType) -> usize {
+    match piece_type {
+        PieceType::Queen => 0,
+        PieceType::Rook => 1,
+        PieceType::Bishop => 2,
+        PieceType::Knight => 3,
+        PieceType::Pawn => 4,
+        PieceType::King => 5,
+    }
+}
+
+fn type_letter(piece_type: PieceType) -> char {
+    match piece_type {
+        PieceType::Queen => 'Q',
+        PieceType::Rook => 'R',
+        PieceType::Bishop => 'B',
+        PieceType::Knight => 'N',
+        PieceType::Pawn => 'P',
+        PieceType::King => 'K',
+    }
+}
+
+// the pieces of the strong side besides its king, the other side has a bare king
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pieces: Vec<PieceType>,
+}
+
+impl Material {
+    pub fn new(mut pieces: Vec<PieceType>) -> Result<Material, BitbaseError> {
+        pieces.sort_by_key(|&piece_type| type_order(piece_type));
+        let material = Material { pieces: pieces };
+        if material.pieces.len() > MAX_PIECES || material.pieces.contains(&PieceType::King) {
+            return Err(BitbaseError::InvalidMaterial(material.name()));
+        }
+        Ok(material)
+    }
+
+    // "KRK", "KBNK" and so on
+    pub fn parse(name: &str) -> Result<Material, BitbaseError> {
+        let invalid = || BitbaseError::InvalidMaterial(name.to_string());
+        let inner = name.strip_prefix('K').and_then(|rest| rest.strip_suffix('K')).ok_or_else(invalid)?;
+        let mut pieces = vec![];
+        for c in inner.chars() {
+            pieces.push(match c {
+                'Q' => PieceType::Queen,
+                'R' => PieceType::Rook,
+                'B' => PieceType::Bishop,
+                'N' => PieceType::Knight,
+                'P' => PieceType::Pawn,
+                _ => return Err(invalid()),
+            });
+        }
+        Material::new(pieces)
+    }
+
+    pub fn name(&self) -> String {
+        let letters: String = self.pieces.iter().map(|&piece_type| type_letter(piece_type)).collect();
+        format!("K{}K", letters)
+    }
+
+    pub fn pieces(&self) -> &[PieceType] {
+        &self.pieces
+    }
+
+    // a bare king, or one with a single minor piece
+    fn can_mate(&self) -> bool {
+        !matches!(self.pieces.as_slice(), [] | [PieceType::Bishop] | [PieceType::Knight])
+    }
+
+    fn has_pawns(&self) -> bool {
+        self.pieces.contains(&PieceType::Pawn)
+    }
+
+    // the strong king is mirrored to files a-d, and without pawns also to ranks 1-4
+    fn king_slots(&self) -> usize {
+        if self.has_pawns() { 32 } else { 16 }
+    }
+
+    pub fn size(&self) -> usize {
+        2 * self.king_slots() * 64 * 64usize.pow(self.pieces.len() as u32)
+    }
+
+    // every table a capture or promotion leads to
+    fn dependencies(&self) -> Vec<Material> {
+        let mut deps = vec![];
+        for i in 0..self.pieces.len() {
+            let mut fewer = self.pieces.clone();
+            let removed = fewer.remove(i);
+            deps.push(Material::new(fewer.clone()).expect("fewer pieces are always valid"));
+            if removed == PieceType::Pawn {
+                for new_piece_type in [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight] {
+                    let mut promoted = fewer.clone();
+                    promoted.push(new_piece_type);
+                    deps.push(Material::new(promoted).expect("same number of pieces"));
+                }
+            }
+        }
+        deps.dedup();
+        deps
+    }
+
+    fn index(&self, pos: &Position) -> Option<usize> {
+        let mut pos = pos.clone();
+        if pos.white_king % 8 >= 4 {
+            pos.mirror(7);
+        }
+        if !self.has_pawns() && pos.white_king / 8 < 4 {
+            pos.mirror(56);
+        }
+        pos.pieces.sort_by_key(|&(piece_type, sq)| (type_order(piece_type), sq));
+        if pos.pieces.len() != self.pieces.len()
+            || pos.pieces.iter().zip(self.pieces.iter()).any(|((piece_type, _), wanted)| piece_type != wanted) {
+            return None;
+        }
+
+        let (x, y) = (pos.white_king % 8, pos.white_king / 8);
+        let slot = if self.has_pawns() { x + 4 * y } else { x + 4 * (y - 4) };
+        let stm = match pos.turn { Color::White => 0, Color::Black => 1 };
+        let mut index = (stm * self.king_slots() + slot) * 64 + pos.black_king;
+        for &(_, sq) in pos.pieces.iter() {
+            index = index * 64 + sq;
+        }
+        Some(index)
+    }
+
+    fn position(&self, mut index: usize) -> Position {
+        let mut squares = vec![0; self.pieces.len()];
+        for sq in squares.iter_mut().rev() {
+            *sq = index % 64;
+            index /= 64;
+        }
+        let black_king = index % 64;
+        index /= 64;
+        let slot = index % self.king_slots();
+        let (x, y) = if self.has_pawns() { (slot % 4, slot / 4) } else { (slot % 4, slot / 4 + 4) };
+        Position {
+            turn: if index / self.king_slots() == 0 { Color::White } else { Color::Black },
+            white_king: x + 8 * y,
+            black_king: black_king,
+            pieces: self.pieces.iter().copied().zip(squares).collect(),
+        }
+    }
+}
+
+// white is the strong side, squares are x + 8 * y like the board
+#[derive(Clone, Debug)]
+struct Position {
+    turn: Color,
+    white_king: usize,
+    black_king: usize,
+    pieces: Vec<(PieceType, usize)>,
+}
+
+impl Position {
+    // 7 mirrors the files and 56 the ranks
+    fn mirror(&mut self, flip: usize) {
+        self.white_king ^= flip;
+        self.black_king ^= flip;
+        for piece in self.pieces.iter_mut() {
+            piece.1 ^= flip;
+        }
+    }
+
+    fn board(&self) -> Option<Board> {
+        let mut board = [[Content::Empty; BOARD_SIZE]; BOARD_SIZE];
+        let kings = [(PieceType::King, self.white_king, Color::White), (PieceType::King, self.black_king, Color::Black)];
+        let pieces = self.pieces.iter().map(|&(piece_type, sq)| (piece_type, sq, Color::White));
+        for (piece_type, sq, color) in kings.into_iter().chain(pieces) {
+            let (x, y) = (sq % 8, sq / 8);
+            if board[y][x] != Content::Empty || (piece_type == PieceType::Pawn && (y == 0 || y == 7)) {
+                return None;
+            }
+            // pawns on their first rank can still move two squares, nothing may castle
+            let times_moved = if piece_type == PieceType::Pawn && y == 6 { 0 } else { 1 };
+            board[y][x] = Content::Occupied(Piece { color: color, piece_type: piece_type, times_moved: times_moved });
+        }
+        Some(board)
+    }
+}
+
+fn xy(sq: usize) -> (usize, usize) {
+    (sq % 8, sq / 8)
+}
+
+fn other(color: Color) -> Color {
+    match color {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+    }
+}
+
+impl Position {
+    fn king(&self, color: Color) -> usize {
+        match color {
+            Color::White => self.white_king,
+            Color::Black => self.black_king,
+        }
+    }
+
+    // squares of every piece of a color, the king first
+    fn squares(&self, color: Color) -> Vec<usize> {
+        let mut squares = vec![self.king(color)];
+        if color == Color::White {
+            squares.extend(self.pieces.iter().map(|&(_, sq)| sq));
+        }
+        squares
+    }
+
+    // the game's board must be this position's
+    fn king_attacked(&self, game: &Game, color: Color) -> bool {
+        let (x, y) = xy(self.king(color));
+        let (other_x, other_y) = xy(self.king(other(color)));
+        // kings are checked by hand, that is most of the work and it is simple
+        if x.abs_diff(other_x) <= 1 && y.abs_diff(other_y) <= 1 {
+            return true;
+        }
+        color == Color::Black && self.pieces.iter().any(|&(_, sq)| game.is_valid_move(xy(sq), (x, y)))
+    }
+
+    fn after_move(&self, from: usize, to: usize, piece_type: PieceType) -> Position {
+        let mut child = self.clone();
+        child.turn = other(self.turn);
+        // only the bare king can capture, the strong side never reaches the other king
+        child.pieces.retain(|&(_, sq)| sq != to);
+        if from == self.white_king {
+            child.white_king = to;
+        } else if from == self.black_king {
+            child.black_king = to;
+        } else if let Some(piece) = child.pieces.iter_mut().find(|(_, sq)| *sq == from) {
+            *piece = (piece_type, to);
+        }
+        child
+    }
+}
+
+// leaves the position on the game's board
+fn is_legal(scratch: &mut Game, pos: &Position) -> bool {
+    match pos.board() {
+        Some(board) => {
+            scratch.board = board;
+            // the side that just moved can not be in check
+            !pos.king_attacked(scratch, other(pos.turn))
+        },
+        None => false,
+    }
+}
+
+/* the positions after every legal move and whether the side to move is in check.
+None if the position itself is not legal */
+fn children(scratch: &mut Game, pos: &Position) -> Option<(bool, Vec<Position>)> {
+    if !is_legal(scratch, pos) {
+        return None;
+    }
+    let board = scratch.board;
+    let mover = pos.turn;
+    let in_check = pos.king_attacked(scratch, mover);
+
+    let mut result = vec![];
+    for from in pos.squares(mover) {
+        scratch.board = board;
+        let destinations = match scratch.get_destinations(xy(from)) {
+            Destinations::Exists(d) => d,
+            Destinations::None => continue,
+        };
+        let this_type = match board[from / 8][from % 8] {
+            Content::Occupied(p) => p.piece_type,
+            Content::Empty => continue,
+        };
+        for (x, y) in destinations {
+            let promotions: &[PieceType] = if this_type == PieceType::Pawn && y == 0 {
+                &[PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight]
+            } else {
+                &[this_type]
+            };
+            for &piece_type in promotions {
+                let child = pos.after_move(from, x + 8 * y, piece_type);
+                scratch.board = child.board().expect("a move never puts two pieces on a square");
+                if !child.king_attacked(scratch, mover) {
+                    result.push(child);
+                }
+            }
+        }
+    }
+    Some((in_check, result))
+}
+
+#[derive(Clone, Debug)]
+pub struct Bitbase {
+    material: Material,
+    values: Vec<u8>,
+}
+
+impl Bitbase {
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn code(&self, pos: &Position) -> Option<u8> {
+        self.material.index(pos).map(|index| self.values[index])
+    }
+
+    /* retrograde analysis: positions are resolved in order of plies to mate,
+    starting from the mates. A position is won in n plies if a move reaches a
+    position lost in n - 1, and lost if every move reaches a won position */
+    fn generate(material: &Material, tables: &Bitbases) -> Bitbase {
+        let size = material.size();
+        let mut values = vec![UNKNOWN; size];
+        // moves inside this table as (child, parent), to walk back from resolved children
+        let mut edges: Vec<(u32, u32)> = vec![];
+        // children not yet known to be won, u16::MAX if a move escapes to a draw
+        let mut remaining = vec![0u16; size];
+        // plies of the slowest win a capture or promotion gives the other side
+        let mut longest_external = vec![0u32; size];
+        // positions to resolve by plies to mate
+        let mut buckets: Vec<Vec<u32>> = vec![vec![]; MAX_PLIES as usize + 2];
+
+        let mut scratch = create_game();
+        for index in 0..size {
+            let pos = material.position(index);
+            // every index maps to many mirrored copies, only the canonical one is used
+            if material.index(&pos) != Some(index) {
+                values[index] = ILLEGAL;
+                continue;
+            }
+            if !material.can_mate() {
+                values[index] = if is_legal(&mut scratch, &pos) { DRAW } else { ILLEGAL };
+                continue;
+            }
+            let (in_check, children) = match children(&mut scratch, &pos) {
+                Some(result) => result,
+                None => {
+                    values[index] = ILLEGAL;
+                    continue;
+                }
+            };
+            if children.is_empty() {
+                if in_check {
+                    buckets[0].push(index as u32);
+                } else {
+                    values[index] = DRAW;
+                }
+                continue;
+            }
+
+            let mut escape = false;
+            let mut internal = 0;
+            for child in children {
+                if let Some(child_index) = material.index(&child) {
+                    edges.push((child_index as u32, index as u32));
+                    internal += 1;
+                    continue;
+                }
+                let code = tables.code(&child).expect("smaller tables are generated first");
+                match probe_from_code(code) {
+                    Some(Probe { wdl: Wdl::Loss, plies_to_mate: Some(plies) }) => buckets[plies as usize + 1].push(index as u32),
+                    Some(Probe { wdl: Wdl::Win, plies_to_mate: Some(plies) }) => {
+                        longest_external[index] = longest_external[index].max(plies);
+                    },
+                    _ => escape = true,
+                }
+            }
+            remaining[index] = if escape { u16::MAX } else { internal };
+            if internal == 0 && !escape {
+                // every move is a capture or promotion that loses
+                buckets[longest_external[index] as usize + 1].push(index as u32);
+            }
+        }
+
+        // the parents of every child, grouped by child
+        edges.sort_unstable();
+        let mut parent_start = vec![0; size + 1];
+        for &(child, _) in edges.iter() {
+            parent_start[child as usize + 1] += 1;
+        }
+        for i in 0..size {
+            parent_start[i + 1] += parent_start[i];
+        }
+
+        for plies in 0..=MAX_PLIES as usize {
+            let bucket = std::mem::take(&mut buckets[plies]);
+            for index in bucket {
+                let index = index as usize;
+                // a position can be queued more than once, the first is the fastest
+                if values[index] != UNKNOWN {
+                    continue;
+                }
+                values[index] = plies as u8 + 1;
+                for &(_, parent) in edges[parent_start[index]..parent_start[index + 1]].iter() {
+                    let parent = parent as usize;
+                    if values[parent] != UNKNOWN {
+                        continue;
+                    }
+                    if plies % 2 == 0 {
+                        // this position is lost, so the parent wins by moving here
+                        buckets[plies + 1].push(parent as u32);
+                    } else if remaining[parent] != u16::MAX {
+                        remaining[parent] -= 1;
+                        if remaining[parent] == 0 {
+                            let longest = (plies as u32).max(longest_external[parent]);
+                            if longest < MAX_PLIES {
+                                buckets[longest as usize + 1].push(parent as u32);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        for value in values.iter_mut() {
+            if *value == UNKNOWN {
+                *value = DRAW;
+            }
+        }
+        Bitbase { material: material.clone(), values: values }
+    }
+    // MAGIC, name length, name, then one byte per position
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let name = self.material.name();
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 1 + name.len() + self.values.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(name.len() as u8);
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(&self.values);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Bitbase, BitbaseError> {
+        if bytes.len() < MAGIC.len() + 1 || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(BitbaseError::BadFile);
+        }
+        let name_end = MAGIC.len() + 1 + bytes[MAGIC.len()] as usize;
+        let name = bytes.get(MAGIC.len() + 1..name_end).ok_or(BitbaseError::BadFile)?;
+        let material = Material::parse(std::str::from_utf8(name).map_err(|_| BitbaseError::BadFile)?)?;
+        let values = bytes[name_end..].to_vec();
+        if values.len() != material.size() {
+            return Err(BitbaseError::BadFile);
+        }
+        Ok(Bitbase { material: material, values: values })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Bitbases {
+    // by material name
+    tables: HashMap<String, Bitbase>,
+}
+
+impl Bitbases {
+    pub fn new() -> Bitbases {
+        Bitbases::default()
+    }
+
+    pub fn contains(&self, material: &Material) -> bool {
+        self.tables.contains_key(&material.name())
+    }
+
+    // generates the table and every smaller table it needs
+    pub fn generate(&mut self, material: &Material) {
+        if self.contains(material) {
+            return;
+        }
+        for dependency in material.dependencies() {
+            self.generate(&dependency);
+        }
+        let table = Bitbase::generate(material, self);
+        self.insert(table);
+    }
+
+    pub fn insert(&mut self, table: Bitbase) {
+        self.tables.insert(table.material.name(), table);
+    }
+
+    fn code(&self, pos: &Position) -> Option<u8> {
+        let mut pieces: Vec<PieceType> = pos.pieces.iter().map(|&(piece_type, _)| piece_type).collect();
+        pieces.sort_by_key(|&piece_type| type_order(piece_type));
+        let name = Material { pieces: pieces }.name();
+        self.tables.get(&name)?.code(pos)
+    }
+
+    // the result for the side to move, None if no table has the position
+    pub fn probe(&self, game: &Game) -> Option<Probe> {
+        let mut white = vec![];
+        let mut black = vec![];
+        for y in 0..BOARD_SIZE {
+            for x in 0..BOARD_SIZE {
+                if let Content::Occupied(p) = game.board[y][x] {
+                    match p.color {
+                        Color::White => white.push((p.piece_type, x + 8 * y)),
+                        Color::Black => black.push((p.piece_type, x + 8 * y)),
+                    }
+                }
+            }
+        }
+        // the tables have white as the strong side, so black's pieces are flipped over
+        let (strong, weak, turn, flip) = if black.len() == 1 {
+            (white, black, game.turn, 0)
+        } else if white.len() == 1 {
+            (black, white, other(game.turn), 56)
+        } else {
+            return None;
+        };
+        let mut pos = Position { turn: turn, white_king: 0, black_king: weak[0].1 ^ flip, pieces: vec![] };
+        for (piece_type, sq) in strong {
+            match piece_type {
+                PieceType::King => pos.white_king = sq ^ flip,
+                _ => pos.pieces.push((piece_type, sq ^ flip)),
+            }
+        }
+        probe_from_code(self.code(&pos)?)
+    }
+
+    /* the fastest win, a draw, or the slowest loss.
+    None if the position is not in the tables */
+    pub fn best_move(&self, game: &Game) -> Option<GameMove> {
+        self.probe(game)?;
+        let mut best: Option<(i64, GameMove)> = None;
+        for mv in game.legal_moves() {
+            let mut after = game.clone();
+            after.make_move(&mv).ok()?;
+            // scored for us, a quicker win and a slower loss are better
+            let score = match self.probe(&after)? {
+                Probe { wdl: Wdl::Loss, plies_to_mate: Some(plies) } => 1000 - plies as i64,
+                Probe { wdl: Wdl::Win, plies_to_mate: Some(plies) } => -1000 + plies as i64,
+                _ => 0,
+            };
+            if best.is_none_or(|(best_score, _)| score > best_score) {
+                best = Some((score, mv));
+            }
+        }
+        best.map(|(_, mv)| mv)
+    }
+
+    pub fn save_dir<P: AsRef<Path>>(&self, dir: P) -> Result<(), BitbaseError> {
+        fs::create_dir_all(&dir)?;
+        for (name, table) in self.tables.iter() {
+            fs::write(dir.as_ref().join(format!("{}.{}", name, FILE_EXTENSION)), table.to_bytes())?;
+        }
+        Ok(())
+    }
+
+    // every table file in the directory
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Bitbases, BitbaseError> {
+        let mut bitbases = Bitbases::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some(FILE_EXTENSION) {
+                bitbases.insert(Bitbase::from_bytes(&fs::read(&path)?)?);
+            }
+        }
+        Ok(bitbases)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(bitbases: &Bitbases, fen: &str) -> Probe {
+        bitbases.probe(&Game::from_fen(fen).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn material_names() {
+        assert_eq!(Material::parse("KNBK").unwrap().name(), "KBNK");
+        assert!(Material::parse("KRRRK").is_err());
+        assert!(Material::parse("KXK").is_err());
+        let material = Material::parse("KPK").unwrap();
+        let pos = material.position(12345);
+        assert_eq!(material.index(&pos), Some(12345));
+    }
+
+    #[test]
+    fn krk_mates() {
+        let mut bitbases = Bitbases::new();
+        bitbases.generate(&Material::parse("KRK").unwrap());
+        // mate in one, and the mated side
+        assert_eq!(probe(&bitbases, "k7/8/1K6/8/8/8/8/7R w - - 0 1"), Probe { wdl: Wdl::Win, plies_to_mate: Some(1) });
+        assert_eq!(probe(&bitbases, "R1k5/8/2K5/8/8/8/8/8 b - - 0 1"), Probe { wdl: Wdl::Loss, plies_to_mate: Some(0) });
+        // stalemate
+        assert_eq!(probe(&bitbases, "8/8/8/8/8/8/1r6/K1k5 w - - 0 1").wdl, Wdl::Draw);
+        // the longest KRK win is 16 moves
+        let longest = bitbases.tables["KRK"].values.iter()
+            .filter_map(|&code| probe_from_code(code))
+            .filter(|probe| probe.wdl == Wdl::Win)
+            .filter_map(|probe| probe.plies_to_mate)
+            .max();
+        assert_eq!(longest, Some(31));
+
+        let game = Game::from_fen("k7/8/1K6/8/8/8/8/7R w - - 0 1").unwrap();
+        assert_eq!(bitbases.best_move(&game), game.parse_uci_move("h1h8"));
+
+        let copy = Bitbase::from_bytes(&bitbases.tables["KRK"].to_bytes()).unwrap();
+        assert_eq!(copy.values, bitbases.tables["KRK"].values);
+    }
+
+    #[test]
+    fn kpk_results() {
+        let mut bitbases = Bitbases::new();
+        bitbases.generate(&Material::parse("KPK").unwrap());
+        // the king in front of its pawn wins, the opposition decides the rest
+        assert_eq!(probe(&bitbases, "8/8/8/8/3K4/3P4/8/3k4 w - - 0 1").wdl, Wdl::Win);
+        assert_eq!(probe(&bitbases, "8/3k4/8/3K4/3P4/8/8/8 w - - 0 1").wdl, Wdl::Draw);
+        assert_eq!(probe(&bitbases, "8/3k4/8/3K4/3P4/8/8/8 b - - 0 1").wdl, Wdl::Loss);
+        // the same position with colors swapped
+        assert_eq!(probe(&bitbases, "8/8/8/3p4/3k4/8/3K4/8 w - - 0 1").wdl, Wdl::Loss);
+    }
+}
@@ -0,0 +1,270 @@
+use std::time::Duration;
+
+use crate::*;
+use crate::fen::FenError;
+use crate::search::{SearchLimits, Searcher};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EpdError {
+    // lines are counted from 1
+    InvalidFen { line: usize, error: FenError },
+    BadOperation { line: usize, text: String },
+    // a bm or am move that is not legal in the position
+    IllegalMove { line: usize, text: String },
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EpdPosition {
+    // the four position fields, without the move counters
+    pub fen: String,
+    // in the order they appear, e.g. ("bm", ["Qxf7+"]), quotes are removed from strings
+    pub operations: Vec<(String, Vec<String>)>,
+    pub line: usize,
+    // a bm or am move the game can not play, e.g. en passant. The suite skips the position
+    pub error: Option<EpdError>,
+}
+
+impl EpdPosition {
+    pub fn operation(&self, opcode: &str) -> Option<&[String]> {
+        self.operations.iter().find(|(op, _)| op == opcode).map(|(_, operands)| operands.as_slice())
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.operation("id").and_then(|operands| operands.first()).map(|id| id.as_str())
+    }
+
+    // the position with the hmvc and fmvn operations applied
+    pub fn game(&self) -> Result<Game, EpdError> {
+        let mut game = Game::from_fen(&self.fen).map_err(|error| EpdError::InvalidFen { line: self.line, error: error })?;
+        let number = |opcode: &str| self.operation(opcode).and_then(|operands| operands.first()).and_then(|n| n.parse().ok());
+        if let Some(halfmove_clock) = number("hmvc") {
+            game.halfmove_clock = halfmove_clock;
+        }
+        if let Some(fullmove_number) = number("fmvn") {
+            game.fullmove_number = fullmove_number;
+        }
+        Ok(game)
+    }
+
+    // the moves of a bm or am operation, in SAN as the standard says but UCI is accepted too
+    pub fn moves(&self, opcode: &str) -> Result<Vec<GameMove>, EpdError> {
+        let game = self.game()?;
+        self.operation(opcode).unwrap_or(&[]).iter()
+            .map(|text| game.parse_san(text)
+                .or_else(|| game.parse_uci_move(text))
+                .ok_or(EpdError::IllegalMove { line: self.line, text: text.clone() }))
+            .collect()
+    }
+}
+
+// "bm Qd1+ Qf3; id \"WAC.001\";" split at the semicolons outside of quotes
+fn parse_operations(text: &str, line: usize) -> Result<Vec<(String, Vec<String>)>, EpdError> {
+    let mut operations = vec![];
+    let mut tokens: Vec<String> = vec![];
+    let mut token = String::new();
+    let mut quoted = false;
+
+    for c in text.chars() {
+        match c {
+            '"' if quoted => {
+                tokens.push(std::mem::take(&mut token));
+                quoted = false;
+            },
+            '"' => quoted = true,
+            _ if quoted => token.push(c),
+            ';' | ' ' | '\t' => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+                if c == ';' && !tokens.is_empty() {
+                    let opcode = tokens.remove(0);
+                    operations.push((opcode, std::mem::take(&mut tokens)));
+                }
+            },
+            _ => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    // the last operation may miss its semicolon, an open quote is an error
+    if quoted {
+        return Err(EpdError::BadOperation { line: line, text: text.to_string() });
+    }
+    if !tokens.is_empty() {
+        let opcode = tokens.remove(0);
+        operations.push((opcode, tokens));
+    }
+    Ok(operations)
+}
+
+/* one position per line, blank lines and lines starting with # are skipped.
+The bm and am moves are checked against the position, a move that does not
+parse is kept as the position's error instead of failing the whole file */
+pub fn parse_epd(text: &str) -> Result<Vec<EpdPosition>, EpdError> {
+    let mut positions = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.splitn(5, char::is_whitespace).collect();
+        if fields.len() < 4 {
+            return Err(EpdError::InvalidFen { line: i + 1, error: FenError::WrongFieldCount });
+        }
+        let mut position = EpdPosition {
+            fen: fields[..4].join(" "),
+            operations: parse_operations(fields.get(4).copied().unwrap_or(""), i + 1)?,
+            line: i + 1,
+            error: None,
+        };
+        position.game()?;
+        position.error = position.moves("bm").and(position.moves("am")).err();
+        positions.push(position);
+    }
+    Ok(positions)
+}
+
+#[derive(Clone, Debug)]
+pub struct EpdResult {
+    pub id: String,
+    pub best_move: Option<GameMove>,
+    pub passed: bool,
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+    pub time: Duration,
+    // set for a skipped position, which is not searched
+    pub error: Option<EpdError>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SuiteReport {
+    pub results: Vec<EpdResult>,
+}
+
+impl SuiteReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|result| result.passed).count()
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &EpdResult> {
+        self.results.iter().filter(|result| !result.passed && result.error.is_none())
+    }
+
+    pub fn skipped(&self) -> impl Iterator<Item = &EpdResult> {
+        self.results.iter().filter(|result| result.error.is_some())
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.results.iter().map(|result| result.nodes).sum()
+    }
+
+    pub fn time(&self) -> Duration {
+        self.results.iter().map(|result| result.time).sum()
+    }
+
+    // e.g. "212/300 passed (70.7%), 1534022 nodes in 301.2s", skipped positions are not counted
+    pub fn summary(&self) -> String {
+        let skipped = self.skipped().count();
+        let total = self.results.len() - skipped;
+        let mut summary = format!("{}/{} passed ({:.1}%), {} nodes in {:.1}s",
+            self.passed(), total, 100.0 * self.passed() as f64 / total.max(1) as f64,
+            self.nodes(), self.time().as_secs_f64());
+        if skipped > 0 {
+            summary += &format!(", {} skipped", skipped);
+        }
+        summary
+    }
+}
+
+/* searches every position with the same limits, a position passes when the
+engine plays one of the bm moves and none of the am moves. The table is
+cleared between positions so every result can be reproduced on its own */
+pub fn run_suite<F: FnMut(&EpdResult)>(positions: &[EpdPosition], searcher: &mut Searcher, limits: SearchLimits, mut on_result: F) -> Result<SuiteReport, EpdError> {
+    let mut report = SuiteReport::default();
+    searcher.set_limits(limits);
+    for position in positions {
+        let id = position.id().map(String::from).unwrap_or_else(|| format!("line {}", position.line));
+        if let Some(error) = &position.error {
+            let result = EpdResult {
+                id: id,
+                best_move: None,
+                passed: false,
+                score: 0,
+                depth: 0,
+                nodes: 0,
+                time: Duration::ZERO,
+                error: Some(error.clone()),
+            };
+            on_result(&result);
+            report.results.push(result);
+            continue;
+        }
+        let game = position.game()?;
+        let best_moves = position.moves("bm")?;
+        let avoid_moves = position.moves("am")?;
+
+        searcher.table().clear();
+        let search = searcher.run(&game);
+        let passed = match search.best_move {
+            Some(mv) => (best_moves.is_empty() || best_moves.contains(&mv)) && !avoid_moves.contains(&mv),
+            None => false,
+        };
+        let result = EpdResult {
+            id: id,
+            best_move: search.best_move,
+            passed: passed,
+            score: search.score,
+            depth: search.depth,
+            nodes: search.nodes,
+            time: search.time,
+            error: None,
+        };
+        on_result(&result);
+        report.results.push(result);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_operations() {
+        let text = "# two positions\n\
+            2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\"; c0 \"mate; in 3\";\n\
+            \n\
+            8/8/8/8/8/8/8/K1k5 w - - hmvc 12; fmvn 40\n";
+        let positions = parse_epd(text).unwrap();
+        assert_eq!(positions.len(), 2);
+        assert_eq!(positions[0].id(), Some("WAC.001"));
+        assert_eq!(positions[0].operation("c0"), Some(&[String::from("mate; in 3")][..]));
+        assert_eq!(positions[0].moves("bm").unwrap()[0].to_uci(), "g3g6");
+        assert_eq!(positions[1].line, 4);
+        assert_eq!(positions[1].game().unwrap().to_fen(), "8/8/8/8/8/8/8/K1k5 w - - 12 40");
+
+        // the position is kept with its error
+        let positions = parse_epd("8/8/8/8/8/8/8/K1k5 w - - bm Kb3;").unwrap();
+        assert_eq!(positions[0].error, Some(EpdError::IllegalMove { line: 1, text: String::from("Kb3") }));
+        assert!(matches!(parse_epd("8/8/8/8/8/8/8/K1k5 w -"), Err(EpdError::InvalidFen { line: 1, .. })));
+    }
+
+    #[test]
+    fn runs_suite() {
+        let text = "6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; id \"mate\";\n\
+            4k3/8/8/3pP3/8/8/8/4K3 w - - bm exd6; id \"en passant\";\n\
+            6k1/5ppp/8/8/8/8/8/R5K1 w - - am Ra8; id \"avoid\";\n";
+        let positions = parse_epd(text).unwrap();
+        let mut searcher = Searcher::new(SearchLimits::default());
+        let mut seen = vec![];
+        let report = run_suite(&positions, &mut searcher, SearchLimits::depth(2), |result| seen.push(result.id.clone())).unwrap();
+        assert_eq!(seen, vec!["mate", "en passant", "avoid"]);
+        assert_eq!(report.passed(), 1);
+        assert_eq!(report.failed().next().unwrap().id, "avoid");
+        assert_eq!(report.skipped().next().unwrap().id, "en passant");
+        assert!(report.summary().starts_with("1/2 passed (50.0%)"));
+        assert!(report.summary().ends_with(", 1 skipped"));
+    }
+}
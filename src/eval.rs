@@ -0,0 +1,399 @@
+use crate::*;
+use crate::bitbase::{Bitbases, Probe, Wdl};
+use crate::search::MATE;
+
+// tables are written from white's side, row 0 is the 8th rank like the board
+type Table = [[i32; 8]; 8];
+
+const PAWN_MG: Table = [
+    [  0,   0,   0,   0,   0,   0,   0,   0],
+    [ 50,  50,  50,  50,  50,  50,  50,  50],
+    [ 10,  10,  20,  30,  30,  20,  10,  10],
+    [  5,   5,  10,  25,  25,  10,   5,   5],
+    [  0,   0,   0,  20,  20,   0,   0,   0],
+    [  5,  -5, -10,   0,   0, -10,  -5,   5],
+    [  5,  10,  10, -20, -20,  10,  10,   5],
+    [  0,   0,   0,   0,   0,   0,   0,   0],
+];
+const PAWN_EG: Table = [
+    [  0,   0,   0,   0,   0,   0,   0,   0],
+    [ 80,  80,  80,  80,  80,  80,  80,  80],
+    [ 50,  50,  50,  50,  50,  50,  50,  50],
+    [ 30,  30,  30,  30,  30,  30,  30,  30],
+    [ 20,  20,  20,  20,  20,  20,  20,  20],
+    [ 10,  10,  10,  10,  10,  10,  10,  10],
+    [ 10,  10,  10,  10,  10,  10,  10,  10],
+    [  0,   0,   0,   0,   0,   0,   0,   0],
+];
+const KNIGHT: Table = [
+    [-50, -40, -30, -30, -30, -30, -40, -50],
+    [-40, -20,   0,   0,   0,   0, -20, -40],
+    [-30,   0,  10,  15,  15,  10,   0, -30],
+    [-30,   5,  15,  20,  20,  15,   5, -30],
+    [-30,   0,  15,  20,  20,  15,   0, -30],
+    [-30,   5,  10,  15,  15,  10,   5, -30],
+    [-40, -20,   0,   5,   5,   0, -20, -40],
+    [-50, -40, -30, -30, -30, -30, -40, -50],
+];
+const BISHOP: Table = [
+    [-20, -10, -10, -10, -10, -10, -10, -20],
+    [-10,   0,   0,   0,   0,   0,   0, -10],
+    [-10,   0,   5,  10,  10,   5,   0, -10],
+    [-10,   5,   5,  10,  10,   5,   5, -10],
+    [-10,   0,  10,  10,  10,  10,   0, -10],
+    [-10,  10,  10,  10,  10,  10,  10, -10],
+    [-10,   5,   0,   0,   0,   0,   5, -10],
+    [-20, -10, -10, -10, -10, -10, -10, -20],
+];
+const ROOK: Table = [
+    [  0,   0,   0,   0,   0,   0,   0,   0],
+    [  5,  10,  10,  10,  10,  10,  10,   5],
+    [ -5,   0,   0,   0,   0,   0,   0,  -5],
+    [ -5,   0,   0,   0,   0,   0,   0,  -5],
+    [ -5,   0,   0,   0,   0,   0,   0,  -5],
+    [ -5,   0,   0,   0,   0,   0,   0,  -5],
+    [ -5,   0,   0,   0,   0,   0,   0,  -5],
+    [  0,   0,   0,   5,   5,   0,   0,   0],
+];
+const QUEEN: Table = [
+    [-20, -10, -10,  -5,  -5, -10, -10, -20],
+    [-10,   0,   0,   0,   0,   0,   0, -10],
+    [-10,   0,   5,   5,   5,   5,   0, -10],
+    [ -5,   0,   5,   5,   5,   5,   0,  -5],
+    [  0,   0,   5,   5,   5,   5,   0,  -5],
+    [-10,   5,   5,   5,   5,   5,   0, -10],
+    [-10,   0,   5,   0,   0,   0,   0, -10],
+    [-20, -10, -10,  -5,  -5, -10, -10, -20],
+];
+const KING_MG: Table = [
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-20, -30, -30, -40, -40, -30, -30, -20],
+    [-10, -20, -20, -20, -20, -20, -20, -10],
+    [ 20,  20,   0,   0,   0,   0,  20,  20],
+    [ 20,  30,  10,   0,   0,  10,  30,  20],
+];
+const KING_EG: Table = [
+    [-50, -40, -30, -20, -20, -30, -40, -50],
+    [-30, -20, -10,   0,   0, -10, -20, -30],
+    [-30, -10,  20,  30,  30,  20, -10, -30],
+    [-30, -10,  30,  40,  40,  30, -10, -30],
+    [-30, -10,  30,  40,  40,  30, -10, -30],
+    [-30, -10,  20,  30,  30,  20, -10, -30],
+    [-30, -30,   0,   0,   0,   0, -30, -30],
+    [-50, -30, -30, -30, -30, -30, -30, -50],
+];
+
+// knights, bishops, rooks and queens left on the board, 24 in the start position
+const MAX_PHASE: i32 = 24;
+
+const DOUBLED_PAWN: (i32, i32) = (-10, -20);
+const ISOLATED_PAWN: (i32, i32) = (-10, -15);
+// by how many ranks the pawn has advanced
+const PASSED_PAWN: [(i32, i32); 8] = [(0, 0), (5, 10), (10, 15), (15, 25), (25, 45), (40, 75), (60, 120), (0, 0)];
+const PAWN_SHIELD: i32 = 10;
+const KING_ZONE_ATTACK: i32 = -8;
+
+// per piece evaluation terms, all from the point of view of one color
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Evaluation {
+    pub material: i32,
+    pub piece_squares: i32,
+    pub pawn_structure: i32,
+    pub king_safety: i32,
+    pub mobility: i32,
+    // 0 in a bare endgame up to MAX_PHASE in the opening
+    pub phase: i32,
+}
+
+impl Evaluation {
+    pub fn total(&self) -> i32 {
+        self.material + self.piece_squares + self.pawn_structure + self.king_safety + self.mobility
+    }
+}
+
+// midgame and endgame score, blended by the phase at the end
+#[derive(Copy, Clone, Default)]
+struct Tapered {
+    mg: i32,
+    eg: i32,
+}
+
+impl Tapered {
+    fn add(&mut self, value: (i32, i32), sign: i32) {
+        self.mg += sign * value.0;
+        self.eg += sign * value.1;
+    }
+    fn blend(&self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+pub fn piece_value(piece_type: PieceType) -> i32 {
+    material_value(piece_type).0
+}
+
+fn material_value(piece_type: PieceType) -> (i32, i32) {
+    match piece_type {
+        PieceType::Pawn => (100, 120),
+        PieceType::Knight => (320, 300),
+        PieceType::Bishop => (330, 320),
+        PieceType::Rook => (500, 550),
+        PieceType::Queen => (950, 1000),
+        PieceType::King => (0, 0),
+    }
+}
+
+fn phase_weight(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::Knight | PieceType::Bishop => 1,
+        PieceType::Rook => 2,
+        PieceType::Queen => 4,
+        _ => 0,
+    }
+}
+
+fn mobility_weight(piece_type: PieceType) -> (i32, i32) {
+    match piece_type {
+        PieceType::Knight => (4, 4),
+        PieceType::Bishop => (5, 5),
+        PieceType::Rook => (2, 4),
+        PieceType::Queen => (1, 2),
+        _ => (0, 0),
+    }
+}
+
+fn piece_square(p: Piece, xy: (usize, usize)) -> (i32, i32) {
+    // mirror the rows for black
+    let row = match p.color {
+        Color::White => xy.1,
+        Color::Black => 7 - xy.1,
+    };
+    let col = xy.0;
+    match p.piece_type {
+        PieceType::Pawn => (PAWN_MG[row][col], PAWN_EG[row][col]),
+        PieceType::Knight => (KNIGHT[row][col], KNIGHT[row][col]),
+        PieceType::Bishop => (BISHOP[row][col], BISHOP[row][col]),
+        PieceType::Rook => (ROOK[row][col], ROOK[row][col]),
+        PieceType::Queen => (QUEEN[row][col], QUEEN[row][col]),
+        PieceType::King => (KING_MG[row][col], KING_EG[row][col]),
+    }
+}
+
+fn sign_for(color: Color, perspective: Color) -> i32 {
+    if color == perspective { 1 } else { -1 }
+}
+
+fn forward(color: Color) -> i32 {
+    match color {
+        Color::White => -1,
+        Color::Black => 1,
+    }
+}
+
+fn pawn_attacks(xy: (usize, usize), color: Color) -> Vec<(usize, usize)> {
+    let y = xy.1 as i32 + forward(color);
+    [xy.0 as i32 - 1, xy.0 as i32 + 1].iter()
+        .filter(|&&x| (0..8).contains(&x) && (0..8).contains(&y))
+        .map(|&x| (x as usize, y as usize))
+        .collect()
+}
+
+pub fn game_phase(game: &Game) -> i32 {
+    let mut phase = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            if let Content::Occupied(p) = game.get_content((x, y)) {
+                phase += phase_weight(p.piece_type);
+            }
+        }
+    }
+    phase.min(MAX_PHASE)
+}
+
+pub fn evaluate(game: &Game, perspective: Color) -> Evaluation {
+    let phase = game_phase(game);
+    let mut material = Tapered::default();
+    let mut piece_squares = Tapered::default();
+    let mut pawn_structure = Tapered::default();
+    let mut king_safety = Tapered::default();
+    let mut mobility = Tapered::default();
+
+    // pawn rows per file and color, used by the pawn structure terms
+    let mut pawns: Vec<(Color, usize, usize)> = vec![];
+    let mut kings: Vec<(Color, (usize, usize))> = vec![];
+
+    for y in 0..8 {
+        for x in 0..8 {
+            if let Content::Occupied(p) = game.get_content((x, y)) {
+                let sign = sign_for(p.color, perspective);
+                material.add(material_value(p.piece_type), sign);
+                piece_squares.add(piece_square(p, (x, y)), sign);
+                match p.piece_type {
+                    PieceType::Pawn => pawns.push((p.color, x, y)),
+                    PieceType::King => kings.push((p.color, (x, y))),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    // PAWN STRUCTURE
+    for &(color, x, y) in &pawns {
+        let sign = sign_for(color, perspective);
+        let own_on_file = pawns.iter().filter(|&&(c, px, _)| c == color && px == x).count();
+        if own_on_file > 1 {
+            // every pawn on a doubled file shares the penalty
+            pawn_structure.add((DOUBLED_PAWN.0 / own_on_file as i32, DOUBLED_PAWN.1 / own_on_file as i32), sign);
+        }
+        let has_neighbour = pawns.iter().any(|&(c, px, _)| c == color && (px as i32 - x as i32).abs() == 1);
+        if !has_neighbour {
+            pawn_structure.add(ISOLATED_PAWN, sign);
+        }
+        let blocked = pawns.iter().any(|&(c, px, py)| {
+            c != color
+                && (px as i32 - x as i32).abs() <= 1
+                && (py as i32 - y as i32) * forward(color) > 0
+        });
+        if !blocked {
+            // rank - 1 from the pawn's own side, 6 on the 7th rank
+            let advanced = match color {
+                Color::White => 7 - y,
+                Color::Black => y,
+            };
+            pawn_structure.add(PASSED_PAWN[advanced], sign);
+        }
+    }
+
+    // MOBILITY, also counting attacks next to the enemy king
+    let mut zone_attacks = [0, 0];
+    for y in 0..8 {
+        for x in 0..8 {
+            if let Content::Occupied(p) = game.get_content((x, y)) {
+                let weight = mobility_weight(p.piece_type);
+                let enemy_king = kings.iter().find(|(c, _)| *c != p.color).map(|(_, xy)| *xy);
+                let d = match game.get_destinations((x, y)) {
+                    Destinations::Exists(d) => d,
+                    Destinations::None => vec![],
+                };
+                if p.piece_type != PieceType::Pawn && p.piece_type != PieceType::King {
+                    let sign = sign_for(p.color, perspective);
+                    mobility.add((weight.0 * d.len() as i32, weight.1 * d.len() as i32), sign);
+                }
+                if let Some(k) = enemy_king {
+                    // pawn destinations are pushes, they attack diagonally
+                    let attacked = match p.piece_type {
+                        PieceType::Pawn => pawn_attacks((x, y), p.color),
+                        _ => d,
+                    };
+                    let near = attacked.iter().filter(|to| {
+                        (to.0 as i32 - k.0 as i32).abs() <= 1 && (to.1 as i32 - k.1 as i32).abs() <= 1
+                    }).count() as i32;
+                    let attacker = match p.color { Color::White => 0, Color::Black => 1 };
+                    zone_attacks[attacker] += near;
+                }
+            }
+        }
+    }
+
+    // KING SAFETY, only matters while there are pieces to attack with
+    for &(color, k) in &kings {
+        let sign = sign_for(color, perspective);
+        let mut shield = 0;
+        for &(c, px, py) in &pawns {
+            let ahead = (py as i32 - k.1 as i32) * forward(color);
+            if c == color && (px as i32 - k.0 as i32).abs() <= 1 && (ahead == 1 || ahead == 2) {
+                shield += 1;
+            }
+        }
+        let attacked_by = match color { Color::White => 1, Color::Black => 0 };
+        king_safety.add((PAWN_SHIELD * shield.min(3) + KING_ZONE_ATTACK * zone_attacks[attacked_by], 0), sign);
+    }
+
+    Evaluation {
+        material: material.blend(phase),
+        piece_squares: piece_squares.blend(phase),
+        pawn_structure: pawn_structure.blend(phase),
+        king_safety: king_safety.blend(phase),
+        mobility: mobility.blend(phase),
+        phase: phase,
+    }
+}
+
+// score from the side to move, used by the search
+pub fn evaluate_for_turn(game: &Game) -> i32 {
+    evaluate(game, game.get_turn()).total()
+}
+
+/* the exact result of small endgames as a score for the side to move,
+wins and losses are mate scores counted from the root */
+pub fn bitbase_score(bitbases: &Bitbases, game: &Game, ply: usize) -> Option<i32> {
+    match bitbases.probe(game)? {
+        Probe { wdl: Wdl::Win, plies_to_mate: Some(plies) } => Some(MATE - ply as i32 - plies as i32),
+        Probe { wdl: Wdl::Loss, plies_to_mate: Some(plies) } => Some(-MATE + ply as i32 + plies as i32),
+        _ => Some(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_position_is_balanced() {
+        let game = create_game();
+        let white = evaluate(&game, Color::White);
+        assert_eq!(white.total(), 0);
+        assert_eq!(white.phase, MAX_PHASE);
+        assert_eq!(evaluate(&game, Color::Black), white);
+    }
+
+    // the start position with everything but the kings taken off, plus one pawn
+    fn lone_pawn(xy: (usize, usize), color: Color) -> Game {
+        let mut game = create_game();
+        for y in [0, 1, 6, 7] {
+            for x in 0..8 {
+                if x != 4 || y == 1 || y == 6 {
+                    game.board[y][x] = Content::Empty;
+                }
+            }
+        }
+        game.board[xy.1][xy.0] = Content::Occupied(Piece { color: color, piece_type: PieceType::Pawn, times_moved: 0 });
+        game
+    }
+
+    #[test]
+    fn passed_pawn_on_the_seventh_rank() {
+        // no pieces, so only the endgame half counts
+        let white = evaluate(&lone_pawn((0, 1), Color::White), Color::White);
+        assert_eq!(white.phase, 0);
+        assert_eq!(white.pawn_structure, ISOLATED_PAWN.1 + PASSED_PAWN[6].1);
+        let black = evaluate(&lone_pawn((0, 6), Color::Black), Color::Black);
+        assert_eq!(black.pawn_structure, white.pawn_structure);
+    }
+
+    #[test]
+    fn pawns_attack_the_king_zone_diagonally() {
+        assert_eq!(pawn_attacks((2, 2), Color::White), vec![(1, 1), (3, 1)]);
+        assert_eq!(pawn_attacks((0, 1), Color::Black), vec![(1, 2)]);
+        assert_eq!(pawn_attacks((4, 7), Color::Black), vec![]);
+    }
+
+    #[test]
+    fn perspectives_are_opposite() {
+        let mut game = create_game();
+        game.move_from_to((4, 6), (4, 4));
+        game.move_from_to((3, 1), (3, 3));
+        game.move_from_to((4, 4), (3, 3));
+
+        let white = evaluate(&game, Color::White);
+        let black = evaluate(&game, Color::Black);
+        assert_eq!(white.total(), -black.total());
+        assert!(white.material > 0);
+        // doubled d pawns
+        assert!(white.pawn_structure < black.pawn_structure);
+        assert_eq!(evaluate_for_turn(&game), black.total());
+    }
+}
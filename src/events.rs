@@ -0,0 +1,232 @@
+use crate::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameOverReason {
+    Checkmate { winner: Color },
+    Stalemate,
+    FiftyMoveRule,
+    Repetition,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    MoveMade { from: (usize, usize), to: (usize, usize), piece: Piece },
+    Capture { square: (usize, usize), piece: Piece },
+    Castle { color: Color, kingside: bool },
+    // the pawn on the square waits for promote
+    PromotionPending { color: Color, square: (usize, usize) },
+    PromotionCompleted { square: (usize, usize), piece_type: PieceType },
+    Check { color: Color },
+    GameOver(GameOverReason),
+    Undo { mv: GameMove },
+}
+
+/* what a recording game keeps next to the position: the events nobody has
+drained yet and the position before every move, for undo */
+#[derive(Clone, Default)]
+pub(crate) struct EventLog {
+    queue: Vec<GameEvent>,
+    undo: Vec<(GameMove, Game)>,
+}
+
+impl Game {
+    /* events and undo are only kept while recording, so the copies the
+    search makes of a game stay cheap. Turning it off drops both */
+    pub fn set_recording(&mut self, recording: bool) {
+        match (recording, self.events.is_some()) {
+            (true, false) => self.events = Some(Box::default()),
+            (false, true) => self.events = None,
+            _ => (),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.events.is_some()
+    }
+
+    /* the same position without the event log. Built field by field, a clone
+    would copy the whole undo stack only to throw it away */
+    pub fn detached(&self) -> Game {
+        Game {
+            size: self.size,
+            board: self.board,
+            turn: self.turn,
+            w_king: self.w_king,
+            b_king: self.b_king,
+            w_check: self.w_check,
+            b_check: self.b_check,
+            must_promote: self.must_promote,
+            hash: self.hash,
+            castle_rights: self.castle_rights,
+            hash_history: self.hash_history.clone(),
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            events: None,
+        }
+    }
+
+    // every event since the last drain, oldest first
+    pub fn drain_events(&mut self) -> Vec<GameEvent> {
+        match self.events.as_mut() {
+            Some(log) => std::mem::take(&mut log.queue),
+            None => vec![],
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.events.as_ref().is_some_and(|log| !log.undo.is_empty())
+    }
+
+    // takes back the last move, a half made promotion included
+    pub fn undo(&mut self) -> Option<GameMove> {
+        let (mv, before) = self.events.as_mut()?.undo.pop()?;
+        let mut log = self.events.take();
+        *self = before;
+        if let Some(log) = log.as_mut() {
+            log.queue.push(GameEvent::Undo { mv: mv });
+        }
+        self.events = log;
+        Some(mv)
+    }
+
+    pub fn game_over_reason(&self) -> Option<GameOverReason> {
+        if let Promotion::MustPromote(_, _) = self.must_promote {
+            return None;
+        }
+        if self.legal_moves().is_empty() {
+            return Some(if self.is_checked(self.turn) {
+                GameOverReason::Checkmate { winner: threats::other(self.turn) }
+            } else {
+                GameOverReason::Stalemate
+            });
+        }
+        if self.halfmove_clock >= 100 {
+            return Some(GameOverReason::FiftyMoveRule);
+        }
+        if self.repetition_count() >= 2 {
+            return Some(GameOverReason::Repetition);
+        }
+        None
+    }
+
+    fn emit(&mut self, event: GameEvent) {
+        if let Some(log) = self.events.as_mut() {
+            log.queue.push(event);
+        }
+    }
+
+    // called by move_from_to before the board changes
+    pub(crate) fn record_move_start(&mut self, from: (usize, usize), to: (usize, usize)) {
+        if self.events.is_none() {
+            return;
+        }
+        let piece = match self.board[from.1][from.0] {
+            Content::Occupied(p) => p,
+            Content::Empty => return,
+        };
+        let before = self.detached();
+        if let Some(log) = self.events.as_mut() {
+            log.undo.push((GameMove { from: from, to: to, promotion: None }, before));
+        }
+        self.emit(GameEvent::MoveMade { from: from, to: to, piece: piece });
+        if let Content::Occupied(captured) = self.board[to.1][to.0] {
+            self.emit(GameEvent::Capture { square: to, piece: captured });
+        }
+        if piece.piece_type == PieceType::King && (to.0 as i32 - from.0 as i32).abs() == 2 {
+            self.emit(GameEvent::Castle { color: piece.color, kingside: to.0 > from.0 });
+        }
+    }
+
+    // called once the move is on the board, and again after a promotion
+    pub(crate) fn record_move_end(&mut self) {
+        if self.events.is_none() {
+            return;
+        }
+        if let Promotion::MustPromote(color, square) = self.must_promote {
+            self.emit(GameEvent::PromotionPending { color: color, square: square });
+            return;
+        }
+        if self.is_checked(self.turn) {
+            self.emit(GameEvent::Check { color: self.turn });
+        }
+        if let Some(reason) = self.game_over_reason() {
+            self.emit(GameEvent::GameOver(reason));
+        }
+    }
+
+    pub(crate) fn record_promotion(&mut self, square: (usize, usize), piece_type: PieceType) {
+        if let Some((mv, _)) = self.events.as_mut().and_then(|log| log.undo.last_mut()) {
+            mv.promotion = Some(piece_type);
+        }
+        self.emit(GameEvent::PromotionCompleted { square: square, piece_type: piece_type });
+        self.record_move_end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(game: &mut Game, uci: &str) {
+        let mv = game.parse_uci_move(uci).unwrap();
+        game.make_move(&mv).unwrap();
+    }
+
+    #[test]
+    fn only_records_when_asked() {
+        let mut game = create_game();
+        play(&mut game, "e2e4");
+        assert!(game.drain_events().is_empty());
+        assert_eq!(game.undo(), None);
+
+        game.set_recording(true);
+        play(&mut game, "e7e5");
+        assert_eq!(game.drain_events().len(), 1);
+        assert!(game.drain_events().is_empty());
+        assert!(!game.detached().is_recording());
+    }
+
+    #[test]
+    fn capture_check_and_mate() {
+        let mut game = create_game();
+        game.set_recording(true);
+        for uci in ["f2f3", "e7e5", "g2g4"] {
+            play(&mut game, uci);
+        }
+        game.drain_events();
+        play(&mut game, "d8h4");
+        let events = game.drain_events();
+        assert!(matches!(events[0], GameEvent::MoveMade { to: (7, 4), .. }));
+        assert_eq!(events[1], GameEvent::Check { color: Color::White });
+        assert_eq!(events[2], GameEvent::GameOver(GameOverReason::Checkmate { winner: Color::Black }));
+
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/r7/R3K3 w - - 0 1").unwrap();
+        game.set_recording(true);
+        play(&mut game, "a1a2");
+        let events = game.drain_events();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[1], GameEvent::Capture { square: (0, 6), piece: Piece { color: Color::Black, piece_type: PieceType::Rook, .. } }));
+    }
+
+    #[test]
+    fn promotion_castle_and_undo() {
+        let mut game = Game::from_fen("4k3/1P6/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        game.set_recording(true);
+        let fen = game.to_fen();
+        play(&mut game, "e1g1");
+        assert!(game.drain_events().contains(&GameEvent::Castle { color: Color::White, kingside: true }));
+        assert_eq!(game.undo().map(|mv| mv.to_uci()), Some(String::from("e1g1")));
+        assert_eq!(game.to_fen(), fen);
+        assert_eq!(game.drain_events().last(), Some(&GameEvent::Undo { mv: game.parse_uci_move("e1g1").unwrap() }));
+
+        // the two step promotion of the GUI
+        game.move_from_to((1, 1), (1, 0));
+        assert_eq!(game.drain_events().last(), Some(&GameEvent::PromotionPending { color: Color::White, square: (1, 0) }));
+        game.promote(PieceType::Queen);
+        let events = game.drain_events();
+        assert_eq!(events[0], GameEvent::PromotionCompleted { square: (1, 0), piece_type: PieceType::Queen });
+        assert_eq!(events[1], GameEvent::Check { color: Color::Black });
+        assert_eq!(game.undo().unwrap().to_uci(), "b7b8q");
+        assert_eq!(game.to_fen(), fen);
+    }
+}
@@ -0,0 +1,408 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::*;
+use crate::search::SearchLimits;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum EngineError {
+    Io(io::Error),
+    // no answer within the timeout
+    Timeout,
+    // the engine closed its output
+    Exited,
+    IllegalMove(String),
+}
+
+impl From<io::Error> for EngineError {
+    fn from(e: io::Error) -> EngineError {
+        EngineError::Io(e)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EngineScore {
+    Centipawns(i32),
+    // moves to mate, negative if the engine is getting mated
+    Mate(i32),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EngineInfo {
+    pub depth: Option<u32>,
+    pub multipv: Option<u32>,
+    pub score: Option<EngineScore>,
+    pub nodes: Option<u64>,
+    // moves as sent by the engine, only the first is checked against our game
+    pub pv: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EngineOutput {
+    pub best_move: Option<GameMove>,
+    pub ponder: Option<String>,
+    // every info line with a score, in the order they came
+    pub infos: Vec<EngineInfo>,
+}
+
+impl EngineOutput {
+    pub fn last_score(&self) -> Option<EngineScore> {
+        self.infos.iter().rev().find_map(|info| info.score)
+    }
+}
+
+pub fn parse_info_line(line: &str) -> Option<EngineInfo> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.first() != Some(&"info") {
+        return None;
+    }
+    let mut info = EngineInfo::default();
+    let mut i = 1;
+    while i < tokens.len() {
+        let next = tokens.get(i + 1);
+        match tokens[i] {
+            "depth" => info.depth = next.and_then(|v| v.parse().ok()),
+            "multipv" => info.multipv = next.and_then(|v| v.parse().ok()),
+            "nodes" => info.nodes = next.and_then(|v| v.parse().ok()),
+            "score" => {
+                let value = tokens.get(i + 2).and_then(|v| v.parse().ok());
+                info.score = match (next, value) {
+                    (Some(&"cp"), Some(v)) => Some(EngineScore::Centipawns(v)),
+                    (Some(&"mate"), Some(v)) => Some(EngineScore::Mate(v)),
+                    _ => None,
+                };
+                i += 1;
+            },
+            "pv" => {
+                info.pv = tokens[i + 1..].iter().map(|mv| mv.to_string()).collect();
+                break;
+            },
+            // the rest of the line is free text
+            "string" => break,
+            _ => {
+                i += 1;
+                continue;
+            }
+        }
+        i += 2;
+    }
+    Some(info)
+}
+
+// (best move, ponder move) from a bestmove line
+pub fn parse_bestmove(line: &str) -> Option<(String, Option<String>)> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    match tokens.as_slice() {
+        ["bestmove", best, "ponder", ponder, ..] => Some((best.to_string(), Some(ponder.to_string()))),
+        ["bestmove", best, ..] => Some((best.to_string(), None)),
+        _ => None,
+    }
+}
+
+pub fn go_command(limits: &SearchLimits) -> String {
+    let mut command = String::from("go");
+    if let Some(depth) = limits.depth {
+        command += &format!(" depth {}", depth);
+    }
+    if let Some(nodes) = limits.nodes {
+        command += &format!(" nodes {}", nodes);
+    }
+    if let Some(movetime) = limits.movetime {
+        command += &format!(" movetime {}", movetime.as_millis());
+    }
+    if command == "go" {
+        // never send a bare go, it means infinite to most engines
+        command += " depth 10";
+    }
+    command
+}
+
+// a UCI engine running as a child process
+pub struct ExternalEngine {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    timeout: Duration,
+    // sent go and has not read its bestmove yet
+    searching: bool,
+    pub name: Option<String>,
+    pub author: Option<String>,
+    // option lines from the handshake, without the leading "option "
+    pub options: Vec<String>,
+}
+
+impl ExternalEngine {
+    // starts the engine and waits for uciok
+    pub fn spawn(program: &str, args: &[&str]) -> Result<ExternalEngine, EngineError> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin was piped");
+        let stdout = child.stdout.take().expect("stdout was piped");
+
+        // a reader thread so we can wait for lines with a timeout
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    },
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let mut engine = ExternalEngine {
+            child: child,
+            stdin: stdin,
+            lines: lines,
+            timeout: DEFAULT_TIMEOUT,
+            searching: false,
+            name: None,
+            author: None,
+            options: vec![],
+        };
+        engine.handshake()?;
+        Ok(engine)
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), EngineError> {
+        writeln!(self.stdin, "{}", line)?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    fn read_line(&self, deadline: Option<Instant>) -> Result<String, EngineError> {
+        let result = match deadline {
+            Some(deadline) => self.lines.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => self.lines.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match result {
+            Ok(line) => Ok(line),
+            Err(RecvTimeoutError::Timeout) => Err(EngineError::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Err(EngineError::Exited),
+        }
+    }
+
+    fn handshake(&mut self) -> Result<(), EngineError> {
+        self.send("uci")?;
+        let deadline = Some(Instant::now() + self.timeout);
+        loop {
+            let line = self.read_line(deadline)?;
+            if line == "uciok" {
+                return Ok(());
+            } else if let Some(name) = line.strip_prefix("id name ") {
+                self.name = Some(name.to_string());
+            } else if let Some(author) = line.strip_prefix("id author ") {
+                self.author = Some(author.to_string());
+            } else if let Some(option) = line.strip_prefix("option ") {
+                self.options.push(option.to_string());
+            }
+        }
+    }
+
+    pub fn is_ready(&mut self) -> Result<(), EngineError> {
+        self.send("isready")?;
+        let deadline = Some(Instant::now() + self.timeout);
+        while self.read_line(deadline)? != "readyok" {}
+        Ok(())
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), EngineError> {
+        self.send(&format!("setoption name {} value {}", name, value))?;
+        self.is_ready()
+    }
+
+    pub fn new_game(&mut self) -> Result<(), EngineError> {
+        self.send("ucinewgame")?;
+        self.is_ready()
+    }
+
+    /* searches the game's current position and waits for bestmove.
+    The position is sent as a FEN, the game does not keep its moves */
+    pub fn analyse(&mut self, game: &Game, limits: &SearchLimits) -> Result<EngineOutput, EngineError> {
+        // a search that timed out can still send its bestmove, it must not answer this one
+        if self.searching {
+            self.send("stop")?;
+            let deadline = Some(Instant::now() + self.timeout);
+            while parse_bestmove(&self.read_line(deadline)?).is_none() {}
+            self.searching = false;
+        }
+        // and any info lines left behind
+        self.is_ready()?;
+
+        self.send(&format!("position fen {}", game.to_fen()))?;
+        self.send(&go_command(limits))?;
+        self.searching = true;
+
+        /* a search without movetime gets self.timeout before it is told to
+        stop, after stop the engine has self.timeout again to send bestmove */
+        let mut deadline = Instant::now() + limits.movetime.unwrap_or(Duration::ZERO) + self.timeout;
+        let mut stopped = false;
+        let mut infos = vec![];
+        loop {
+            let line = match self.read_line(Some(deadline)) {
+                Err(EngineError::Timeout) if !stopped => {
+                    self.send("stop")?;
+                    stopped = true;
+                    deadline = Instant::now() + self.timeout;
+                    continue;
+                },
+                line => line?,
+            };
+            if let Some((best, ponder)) = parse_bestmove(&line) {
+                self.searching = false;
+                let best_move = match best.as_str() {
+                    "0000" | "(none)" => None,
+                    _ => Some(game.parse_uci_move(&best).ok_or(EngineError::IllegalMove(best))?),
+                };
+                return Ok(EngineOutput { best_move: best_move, ponder: ponder, infos: infos });
+            }
+            if let Some(info) = parse_info_line(&line) {
+                if info.score.is_some() {
+                    infos.push(info);
+                }
+            }
+        }
+    }
+
+    // the engine's move as an opponent
+    pub fn best_move(&mut self, game: &Game, limits: &SearchLimits) -> Result<Option<GameMove>, EngineError> {
+        Ok(self.analyse(game, limits)?.best_move)
+    }
+
+    pub fn quit(mut self) {
+        self.send("quit").ok();
+        // give it a moment before killing it
+        let deadline = Instant::now() + self.timeout;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+impl Drop for ExternalEngine {
+    fn drop(&mut self) {
+        // kill does nothing if quit already reaped the process
+        if let Ok(None) = self.child.try_wait() {
+            self.child.kill().ok();
+            self.child.wait().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a tiny engine that always plays e2e4
+    const FAKE_ENGINE: &str = r#"
+        while read line; do
+            case "$line" in
+                uci) echo "id name Fake"; echo "option name Hash type spin default 1 min 1 max 8"; echo "uciok";;
+                isready) echo "readyok";;
+                go*) echo "info depth 1 score cp 15 nodes 20 pv e2e4"; echo "info depth 2 score mate -3 pv e2e4 e7e5"; echo "bestmove e2e4 ponder e7e5";;
+                quit) exit 0;;
+            esac
+        done
+    "#;
+
+    // thinks until it is told to stop
+    const SLOW_ENGINE: &str = r#"
+        while read line; do
+            case "$line" in
+                uci) echo "uciok";;
+                isready) echo "readyok";;
+                stop) echo "bestmove d2d4";;
+                quit) exit 0;;
+            esac
+        done
+    "#;
+
+    // answers its first search after the timeout and ignores stop
+    const LATE_ENGINE: &str = r#"
+        searches=0
+        while read line; do
+            case "$line" in
+                uci) echo "uciok";;
+                isready) echo "readyok";;
+                go*) searches=$((searches + 1))
+                    if [ $searches = 1 ]; then (sleep 0.5; echo "bestmove e2e4") & else echo "bestmove d2d4"; fi;;
+                quit) exit 0;;
+            esac
+        done
+    "#;
+
+    #[test]
+    fn parses_engine_lines() {
+        let info = parse_info_line("info depth 12 seldepth 20 multipv 1 score cp -31 nodes 4000 nps 100 pv e2e4 e7e5").unwrap();
+        assert_eq!(info.depth, Some(12));
+        assert_eq!(info.multipv, Some(1));
+        assert_eq!(info.score, Some(EngineScore::Centipawns(-31)));
+        assert_eq!(info.pv, vec!["e2e4", "e7e5"]);
+        assert_eq!(parse_info_line("info string hello depth 3").unwrap().depth, None);
+        assert_eq!(parse_bestmove("bestmove g1f3"), Some((String::from("g1f3"), None)));
+        assert_eq!(go_command(&SearchLimits::depth(4)), "go depth 4");
+    }
+
+    #[test]
+    fn plays_against_fake_engine() {
+        let mut engine = ExternalEngine::spawn("sh", &["-c", FAKE_ENGINE]).unwrap();
+        assert_eq!(engine.name.as_deref(), Some("Fake"));
+        assert_eq!(engine.options.len(), 1);
+        engine.set_option("Hash", "4").unwrap();
+        engine.new_game().unwrap();
+
+        let output = engine.analyse(&create_game(), &SearchLimits::movetime(Duration::from_millis(100))).unwrap();
+        assert_eq!(output.best_move, Some(GameMove { from: (4, 6), to: (4, 4), promotion: None }));
+        assert_eq!(output.ponder.as_deref(), Some("e7e5"));
+        assert_eq!(output.last_score(), Some(EngineScore::Mate(-3)));
+
+        // e2e4 is not legal for black
+        let mut game = create_game();
+        game.move_from_to((3, 6), (3, 4));
+        assert!(matches!(engine.best_move(&game, &SearchLimits::depth(1)), Err(EngineError::IllegalMove(_))));
+        engine.quit();
+    }
+
+    #[test]
+    fn stops_a_slow_engine() {
+        let mut engine = ExternalEngine::spawn("sh", &["-c", SLOW_ENGINE]).unwrap();
+        engine.set_timeout(Duration::from_millis(200));
+        let output = engine.analyse(&create_game(), &SearchLimits::depth(30)).unwrap();
+        assert_eq!(output.best_move, Some(GameMove { from: (3, 6), to: (3, 4), promotion: None }));
+        engine.quit();
+    }
+
+    #[test]
+    fn ignores_a_late_bestmove() {
+        let mut engine = ExternalEngine::spawn("sh", &["-c", LATE_ENGINE]).unwrap();
+        engine.set_timeout(Duration::from_millis(100));
+        assert!(matches!(engine.analyse(&create_game(), &SearchLimits::depth(1)), Err(EngineError::Timeout)));
+        // the old bestmove is waiting when the next search starts
+        thread::sleep(Duration::from_millis(600));
+        let output = engine.analyse(&create_game(), &SearchLimits::depth(1)).unwrap();
+        assert_eq!(output.best_move, Some(GameMove { from: (3, 6), to: (3, 4), promotion: None }));
+        engine.quit();
+    }
+}
@@ -0,0 +1,278 @@
+use crate::*;
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FenError {
+    WrongFieldCount,
+    InvalidBoard,
+    InvalidTurn,
+    InvalidCastling,
+    InvalidEnPassant,
+    InvalidNumber,
+    MissingKing(Color),
+}
+
+// (x, y) with y = 0 on the 8th rank, like the board
+pub fn square_name(xy: (usize, usize)) -> String {
+    format!("{}{}", (b'a' + xy.0 as u8) as char, 8 - xy.1)
+}
+
+pub fn parse_square(text: &str) -> Option<(usize, usize)> {
+    let bytes = text.as_bytes();
+    if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1]) {
+        return None;
+    }
+    Some(((bytes[0] - b'a') as usize, (b'8' - bytes[1]) as usize))
+}
+
+pub fn piece_char(p: Piece) -> char {
+    let c = match p.piece_type {
+        PieceType::Pawn => 'p',
+        PieceType::Knight => 'n',
+        PieceType::Bishop => 'b',
+        PieceType::Rook => 'r',
+        PieceType::Queen => 'q',
+        PieceType::King => 'k',
+    };
+    match p.color {
+        Color::White => c.to_ascii_uppercase(),
+        Color::Black => c,
+    }
+}
+
+pub fn piece_type_from_char(c: char) -> Option<PieceType> {
+    match c.to_ascii_lowercase() {
+        'p' => Some(PieceType::Pawn),
+        'n' => Some(PieceType::Knight),
+        'b' => Some(PieceType::Bishop),
+        'r' => Some(PieceType::Rook),
+        'q' => Some(PieceType::Queen),
+        'k' => Some(PieceType::King),
+        _ => None,
+    }
+}
+
+impl GameMove {
+    // long algebraic notation as used by UCI, e.g. e2e4 or e7e8q
+    pub fn to_uci(&self) -> String {
+        let mut text = square_name(self.from) + &square_name(self.to);
+        if let Some(new_piece_type) = self.promotion {
+            text.push(piece_char(Piece {
+                color: Color::Black,
+                piece_type: new_piece_type,
+                times_moved: 0
+            }));
+        }
+        text
+    }
+}
+
+impl Game {
+    pub fn from_fen(fen: &str) -> Result<Game, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        // the move counters are often left out
+        if fields.len() < 4 || fields.len() > 6 {
+            return Err(FenError::WrongFieldCount);
+        }
+
+        let mut game = create_game();
+        game.board = [[Content::Empty; BOARD_SIZE]; BOARD_SIZE];
+
+        // BOARD
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::InvalidBoard);
+        }
+        for (y, rank) in ranks.iter().enumerate() {
+            let mut x = 0;
+            for c in rank.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    x += skip as usize;
+                } else {
+                    let piece_type = piece_type_from_char(c).ok_or(FenError::InvalidBoard)?;
+                    if x >= 8 {
+                        return Err(FenError::InvalidBoard);
+                    }
+                    let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+                    // only pawns on their first rank may still move two squares
+                    let start_rank = match color { Color::White => 6, Color::Black => 1 };
+                    let times_moved = if piece_type == PieceType::Pawn && y == start_rank { 0 } else { 1 };
+                    game.board[y][x] = Content::Occupied(Piece {
+                        color: color,
+                        piece_type: piece_type,
+                        times_moved: times_moved
+                    });
+                    x += 1;
+                }
+            }
+            if x != 8 {
+                return Err(FenError::InvalidBoard);
+            }
+        }
+        for color in [Color::White, Color::Black] {
+            let kings = game.board.iter().flatten().filter(|c| match c {
+                Content::Occupied(p) => p.color == color && p.piece_type == PieceType::King,
+                Content::Empty => false,
+            }).count();
+            if kings != 1 {
+                return Err(FenError::MissingKing(color));
+            }
+        }
+
+        // TURN
+        game.turn = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            _ => return Err(FenError::InvalidTurn),
+        };
+
+        // CASTLING, the king and rook of every right are marked as unmoved
+        if fields[2] != "-" {
+            for c in fields[2].chars() {
+                let (color, rook_x) = match c {
+                    'K' => (Color::White, 7),
+                    'Q' => (Color::White, 0),
+                    'k' => (Color::Black, 7),
+                    'q' => (Color::Black, 0),
+                    _ => return Err(FenError::InvalidCastling),
+                };
+                let y = match color { Color::White => 7, Color::Black => 0 };
+                for (x, piece_type) in [(4, PieceType::King), (rook_x, PieceType::Rook)] {
+                    match game.board[y][x] {
+                        Content::Occupied(mut p) if p.color == color && p.piece_type == piece_type => {
+                            p.times_moved = 0;
+                            game.board[y][x] = Content::Occupied(p);
+                        },
+                        _ => return Err(FenError::InvalidCastling),
+                    }
+                }
+            }
+        }
+
+        // EN PASSANT is not supported by the game, only checked for syntax
+        if fields[3] != "-" && parse_square(fields[3]).is_none() {
+            return Err(FenError::InvalidEnPassant);
+        }
+
+        game.halfmove_clock = match fields.get(4) {
+            Some(n) => n.parse().map_err(|_| FenError::InvalidNumber)?,
+            None => 0,
+        };
+        game.fullmove_number = match fields.get(5) {
+            Some(n) => n.parse().map_err(|_| FenError::InvalidNumber)?,
+            None => 1,
+        };
+
+        game.sync_state();
+        Ok(game)
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for y in 0..self.size {
+            let mut empty = 0;
+            for x in 0..self.size {
+                match self.board[y][x] {
+                    Content::Empty => empty += 1,
+                    Content::Occupied(p) => {
+                        if empty > 0 {
+                            fen += &empty.to_string();
+                            empty = 0;
+                        }
+                        fen.push(piece_char(p));
+                    }
+                }
+            }
+            if empty > 0 {
+                fen += &empty.to_string();
+            }
+            if y + 1 < self.size {
+                fen.push('/');
+            }
+        }
+
+        fen += match self.turn {
+            Color::White => " w ",
+            Color::Black => " b ",
+        };
+
+        fen += &self.castling_field();
+        fen += &format!(" - {} {}", self.halfmove_clock, self.fullmove_number);
+        fen
+    }
+
+    // the castling rights as in FEN, "-" for none
+    pub fn castling_field(&self) -> String {
+        let rights = self.castle_rights_mask();
+        let mut castling = String::new();
+        for (bit, c) in [(2, 'K'), (1, 'Q'), (8, 'k'), (4, 'q')] {
+            if rights & bit != 0 {
+                castling.push(c);
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        castling
+    }
+
+    // a legal move for the side to move in UCI notation
+    pub fn parse_uci_move(&self, text: &str) -> Option<GameMove> {
+        if text.len() < 4 || text.len() > 5 || !text.is_ascii() {
+            return None;
+        }
+        let from = parse_square(&text[0..2])?;
+        let to = parse_square(&text[2..4])?;
+        let promotion = match text.chars().nth(4) {
+            Some(c) => Some(piece_type_from_char(c)?),
+            None => None,
+        };
+        let mv = GameMove { from: from, to: to, promotion: promotion };
+        if self.legal_moves().contains(&mv) { Some(mv) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_position_round_trip() {
+        let game = Game::from_fen(START_FEN).unwrap();
+        assert_eq!(game.to_fen(), START_FEN);
+        assert_eq!(create_game().to_fen(), START_FEN);
+        assert_eq!(game.hash(), create_game().hash());
+    }
+
+    #[test]
+    fn castling_and_counters() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 12 40";
+        let game = Game::from_fen(fen).unwrap();
+        assert_eq!(game.to_fen(), fen);
+        assert_eq!(game.get_turn(), Color::Black);
+        let castle = GameMove { from: (4, 0), to: (2, 0), promotion: None };
+        assert!(game.legal_moves().contains(&castle));
+        assert!(!game.legal_moves().contains(&GameMove { from: (4, 0), to: (6, 0), promotion: None }));
+    }
+
+    #[test]
+    fn moves_update_fen() {
+        let mut game = create_game();
+        let mv = game.parse_uci_move("e2e4").unwrap();
+        game.make_move(&mv).unwrap();
+        game.make_move(&game.parse_uci_move("g8f6").unwrap()).unwrap();
+        game.make_move(&game.parse_uci_move("e1e2").unwrap()).unwrap();
+        assert_eq!(game.to_fen(), "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 2 2");
+        assert_eq!(game.parse_uci_move("e7e4"), None);
+        assert_eq!(mv.to_uci(), "e2e4");
+    }
+
+    #[test]
+    fn rejects_bad_fen() {
+        assert_eq!(Game::from_fen("8/8/8/8/8/8/8/8 w - - 0 1").err(), Some(FenError::MissingKing(Color::White)));
+        assert_eq!(Game::from_fen("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").err(), Some(FenError::InvalidBoard));
+        assert_eq!(Game::from_fen("4k3/8/8/8/8/8/8/4K3 w K - 0 1").err(), Some(FenError::InvalidCastling));
+        assert_eq!(Game::from_fen("4k3/8/8/8/8/8/8/4K3 x - - 0 1").err(), Some(FenError::InvalidTurn));
+    }
+}
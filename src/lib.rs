@@ -1,22 +1,58 @@
 mod piece;
 mod content;
-mod netcode;
+pub mod netcode;
+pub mod search;
+pub mod eval;
+pub mod tt;
+pub mod fen;
+pub mod pgn;
+pub mod epd;
+pub mod puzzle;
+pub mod puzzle_extract;
+pub mod analysis;
+pub mod threats;
+pub mod see;
+pub mod events;
+pub mod position;
+pub mod text_board;
+pub mod tui;
+pub mod svg;
+pub mod uci;
+pub mod xboard;
+pub mod external_engine;
+mod zobrist;
+pub mod polyglot;
+pub mod bitbase;
+mod polyglot_keys;
 pub mod net_packet;
 pub use piece::*;
 pub use content::*;
 
 use Definitions::*;
 
+const BOARD_SIZE: usize = 8;
+// a plain array so cloning a game is a single copy
+type Board = [[Content; BOARD_SIZE]; BOARD_SIZE];
+
 #[derive(Clone)]
 pub struct Game {
     size: usize,
-    board: Vec<Vec<Content>>,
+    board: Board,
     turn: Color,   
     w_king: (usize, usize),
     b_king: (usize, usize),
     w_check: bool,
     b_check: bool,
     must_promote: Promotion,
+    hash: u64,
+    castle_rights: u8,
+    // hashes of every earlier position, for repetition checks
+    hash_history: Vec<u64>,
+    // moves since the last capture or pawn move, and the FEN move number
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    // None unless someone asked for events, see events.rs
+    events: Option<Box<events::EventLog>>,
 }
 
 
@@ -33,19 +69,25 @@ impl Game {
         self.w_check = false;
         self.b_check = false;
         self.must_promote = Promotion::None;
+        self.turn = Color::White;
+        self.castle_rights = self.castle_rights_mask();
+        self.hash = self.compute_hash();
+        self.hash_history.clear();
+        self.halfmove_clock = 0;
+        self.fullmove_number = 1;
     }
-    fn create_rank2(&self, color: Color) -> Vec<Content> {
-        vec![
+    fn create_rank2(&self, color: Color) -> [Content; BOARD_SIZE] {
+        [
             Content::Occupied(Piece {
                 color: color,
                 piece_type: PieceType:: Pawn,
                 times_moved: 0
             });
-            self.size   
+            BOARD_SIZE
         ]
     }
-    fn create_rank1(&self, color: Color) -> Vec<Content> {
-        vec![
+    fn create_rank1(&self, color: Color) -> [Content; BOARD_SIZE] {
+        [
             Content::Occupied(Piece {
                 color: color,
                 piece_type: PieceType:: Rook,
@@ -88,18 +130,9 @@ impl Game {
             })
         ]
     }
+    // prints the board, to_text gives it as a string with more options
     pub fn draw(&self) {
-        println!("   0 1 2 3 4 5 6 7");
-        let mut i = 0;
-        for row in &self.board {
-            print!("{} ", i);
-            for square in row {
-                print!("|{}", square.get_symbol());
-            }
-            println!("|");
-            i += 1;
-        }
-        println!("");
+        println!("{}", self.to_text(&text_board::TextBoardOptions { unicode: true, ..Default::default() }));
     }
 
     fn destination_outside_board(&self, x: i32, y: i32) -> bool {
@@ -184,7 +217,7 @@ impl Game {
         ];
         return CanCastle {
             left: first_rank[0..=4] == left_side,
-            right: first_rank[0..=7] == right_side
+            right: first_rank[4..=7] == right_side
         };
 
     }
@@ -358,19 +391,71 @@ impl Game {
                     Content::Empty => panic!("can not promote empty square!"),
                     Content::Occupied(this_p) => {
                         let cur_times_moved = this_p.times_moved;
-                        self.board[xy.1][xy.0] = Content::Occupied(Piece {
+                        self.set_content(xy, Content::Occupied(Piece {
                             color: color,
                             piece_type: new_piece_type,
                             times_moved: cur_times_moved
-                        });
+                        }));
                         self.must_promote = Promotion::None;
                         self.next_turn();
+                        self.record_promotion(xy, new_piece_type);
                     }
                 }
             }
         }
     }
 
+    // a pawn moving onto its last rank has to be promoted as part of the move
+    pub fn is_promotion_move(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        match self.board[from.1][from.0] {
+            Content::Occupied(this_p) => {
+                let last_rank: usize = match this_p.color {
+                    Color::Black => 7,
+                    Color::White => 0,
+                };
+                this_p.piece_type == PieceType::Pawn && to.1 == last_rank
+            },
+            Content::Empty => false,
+        }
+    }
+
+    /* makes a move and its promotion in one step, matching the
+    promotion field of the network Move message. Nothing is changed
+    if the move is rejected */
+    pub fn move_with_promotion(&mut self, from: (usize, usize), to: (usize, usize), promotion: Option<PieceType>) -> Result<(), MoveError> {
+        match self.must_promote {
+            Promotion::MustPromote(_, _) => return Err(MoveError::MustPromote),
+            Promotion::None => ()
+        }
+        if self.destination_outside_board(from.0 as i32, from.1 as i32)
+            || self.destination_outside_board(to.0 as i32, to.1 as i32) {
+            return Err(MoveError::IllegalMove);
+        }
+        match self.board[from.1][from.0] {
+            Content::Empty => return Err(MoveError::IllegalMove),
+            Content::Occupied(this_p) => {
+                if this_p.color != self.turn {
+                    return Err(MoveError::NotYourTurn);
+                }
+            }
+        }
+        if !self.is_valid_move(from, to) {
+            return Err(MoveError::IllegalMove);
+        }
+        match (self.is_promotion_move(from, to), promotion) {
+            (true, None) => return Err(MoveError::MissingPromotion),
+            (false, Some(_)) => return Err(MoveError::UnexpectedPromotion),
+            (true, Some(PieceType::Pawn)) | (true, Some(PieceType::King)) => return Err(MoveError::InvalidPromotion),
+            _ => (),
+        }
+
+        self.move_from_to(from, to);
+        if let Some(new_piece_type) = promotion {
+            self.promote(new_piece_type);
+        }
+        Ok(())
+    }
+
     pub fn move_from_to(&mut self, from: (usize, usize), to: (usize, usize)) {
         // CANT MAKE MOVE BEFORE PROMOTION
         match self.must_promote {
@@ -387,10 +472,18 @@ impl Game {
 
                             // double check that move is legal
                             if d.contains(&to) {
+                                self.record_move_start(from, to);
+                                self.hash_history.push(self.hash);
+                                let is_capture = self.board[to.1][to.0] != Content::Empty;
+                                if is_capture || this_p.piece_type == PieceType::Pawn {
+                                    self.halfmove_clock = 0;
+                                } else {
+                                    self.halfmove_clock += 1;
+                                }
                                 
                                 // UPDATE TIMES MOVED IN CURRENT PIECE
                                 this_p.times_moved += 1;
-                                self.board[from.1][from.0] = Content::Occupied(this_p);
+                                self.set_content(from, Content::Occupied(this_p));
 
                                 match this_p.piece_type {
                                     PieceType::King => {
@@ -413,14 +506,14 @@ impl Game {
                                             self.next_turn();
                                         } else {
                                             // regular king move
-                                            self.board[to.1][to.0] = self.board[from.1][from.0];
-                                            self.board[from.1][from.0] = Content::Empty;
+                                            self.set_content(to, self.board[from.1][from.0]);
+                                            self.set_content(from, Content::Empty);
                                             self.next_turn();
                                         }
                                     },
                                     PieceType::Pawn => {
-                                        self.board[to.1][to.0] = self.board[from.1][from.0];
-                                        self.board[from.1][from.0] = Content::Empty;
+                                        self.set_content(to, self.board[from.1][from.0]);
+                                        self.set_content(from, Content::Empty);
                                         
                                         // last rank is row index of oponent's first rank
                                         let last_rank: usize = match this_p.color {
@@ -438,11 +531,12 @@ impl Game {
                                     },
                                     _ => {
                                         // regular move
-                                        self.board[to.1][to.0] = self.board[from.1][from.0];
-                                        self.board[from.1][from.0] = Content::Empty;
+                                        self.set_content(to, self.board[from.1][from.0]);
+                                        self.set_content(from, Content::Empty);
                                         self.next_turn();
                                     },
                                 }
+                                self.record_move_end();
 
                             } else {
                                 panic!("This move is not legal!");
@@ -462,77 +556,141 @@ impl Game {
             Color::Black => 0,
             Color::White => 7,
         };
-        self.board[y][0] = Content::Empty;
-        self.board[y][2] = Content::Occupied(Piece {
+        self.set_content((0, y), Content::Empty);
+        self.set_content((2, y), Content::Occupied(Piece {
             color: color,
             piece_type: PieceType::King,
             times_moved: 1
-        });
-        self.board[y][3] = Content::Occupied(Piece {
+        }));
+        self.set_content((3, y), Content::Occupied(Piece {
             color: color,
             piece_type: PieceType::Rook,
             times_moved: 1
-        });
-        self.board[y][4] = Content::Empty;
+        }));
+        self.set_content((4, y), Content::Empty);
     }
     fn castle_right(&mut self, color: Color) {
         let y: usize = match color {
             Color::Black => 0,
             Color::White => 7,
         };
-        self.board[y][4] = Content::Empty;
-        self.board[y][5] = Content::Occupied(Piece {
+        self.set_content((4, y), Content::Empty);
+        self.set_content((5, y), Content::Occupied(Piece {
             color: color,
             piece_type: PieceType::Rook,
             times_moved: 1
-        });
-        self.board[y][6] = Content::Occupied(Piece {
+        }));
+        self.set_content((6, y), Content::Occupied(Piece {
             color: color,
             piece_type: PieceType::King,
             times_moved: 1
-        });
-        self.board[y][7] = Content::Empty;
+        }));
+        self.set_content((7, y), Content::Empty);
         
     }
 
     fn next_turn(&mut self) {
         self.check_check();
         match self.turn {
-            Color::Black => self.turn = Color::White,
+            Color::Black => {
+                self.turn = Color::White;
+                self.fullmove_number += 1;
+            },
             Color::White => self.turn = Color::Black
         }
+        // KEEPING THE HASH UP TO DATE
+        self.hash ^= zobrist::SIDE_KEY;
+        let new_rights = self.castle_rights_mask();
+        self.hash ^= zobrist::castle_key(self.castle_rights) ^ zobrist::castle_key(new_rights);
+        self.castle_rights = new_rights;
     }
 
-    fn is_threatened(&self, xy: (usize, usize)) -> bool {
-        match self.board[xy.1][xy.0] {
-            Content::Occupied(this_p) => {
-                
-                let mut threatened = false;
+    // every board write goes through here so the hash follows the board
+    fn set_content(&mut self, xy: (usize, usize), content: Content) {
+        if let Content::Occupied(old_p) = self.board[xy.1][xy.0] {
+            self.hash ^= zobrist::piece_key(old_p, xy);
+        }
+        if let Content::Occupied(new_p) = content {
+            self.hash ^= zobrist::piece_key(new_p, xy);
+        }
+        self.board[xy.1][xy.0] = content;
+    }
 
+    // bit 0/1: white left/right, bit 2/3: black left/right, king and rook unmoved
+    fn castle_rights_mask(&self) -> u8 {
+        let mut mask = 0;
+        for (i, (color, y)) in [(Color::White, 7), (Color::Black, 0)].into_iter().enumerate() {
+            let unmoved = |x: usize, piece_type: PieceType| self.board[y][x] == Content::Occupied(Piece {
+                color: color,
+                piece_type: piece_type,
+                times_moved: 0
+            });
+            if unmoved(4, PieceType::King) {
+                if unmoved(0, PieceType::Rook) {
+                    mask |= 1 << (2 * i);
+                }
+                if unmoved(7, PieceType::Rook) {
+                    mask |= 2 << (2 * i);
+                }
+            }
+        }
+        mask
+    }
 
-                let mut x = 0;
-                let mut y = 0;
-                for row in & self.board {
-                    for square in row {
-                        match square {
-                            Content::Occupied(other_p) => {
-                                match self.get_destinations((x as usize, y as usize)) {
-                                    Destinations::Exists(d) => {
-                                        if this_p.color != other_p.color && d.contains(&xy) {
-                                            threatened = true;
-                                        }
-                                    },
-                                    Destinations::None => (),
-                                }
-                            },
-                            Content::Empty => (),
+    pub fn compute_hash(&self) -> u64 {
+        let mut hash = 0;
+        for y in 0..self.size {
+            for x in 0..self.size {
+                if let Content::Occupied(p) = self.board[y][x] {
+                    hash ^= zobrist::piece_key(p, (x, y));
+                }
+            }
+        }
+        if self.turn == Color::Black {
+            hash ^= zobrist::SIDE_KEY;
+        }
+        hash ^ zobrist::castle_key(self.castle_rights_mask())
+    }
+
+    // recomputes everything derived from the board after setting up a position
+    fn sync_state(&mut self) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                if let Content::Occupied(p) = self.board[y][x] {
+                    if p.piece_type == PieceType::King {
+                        match p.color {
+                            Color::White => self.w_king = (x, y),
+                            Color::Black => self.b_king = (x, y),
                         }
-                        x += 1;
                     }
-                    x = 0;
-                    y += 1;
                 }
-                return threatened;
+            }
+        }
+        self.must_promote = Promotion::None;
+        self.check_check();
+        self.castle_rights = self.castle_rights_mask();
+        self.hash = self.compute_hash();
+        self.hash_history.clear();
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    // how many times the current position has been seen before
+    pub fn repetition_count(&self) -> usize {
+        self.hash_history.iter().filter(|&&h| h == self.hash).count()
+    }
+
+    // attacked by the other side than the piece standing on xy
+    fn is_threatened(&self, xy: (usize, usize)) -> bool {
+        match self.board[xy.1][xy.0] {
+            Content::Occupied(this_p) => {
+                let other_color = match this_p.color {
+                    Color::White => Color::Black,
+                    Color::Black => Color::White,
+                };
+                self.is_attacked_by(xy, other_color)
             },
             Content::Empty => false
         }
@@ -574,7 +732,7 @@ impl Game {
                                 Destinations::Exists(dest_vecs) => {
                                     for dest_vec in dest_vecs {
 
-                                        let mut tmp_game = self.clone();
+                                        let mut tmp_game = self.detached();
                                         // OBS need to prevent tmp game from panicing "not your turn"
                                         // so the tmp_games turn has to be set to color of current piece
                                         tmp_game.turn = this_p.color;
@@ -641,20 +799,93 @@ impl Game {
         return self.board[pos.1][pos.0]
     }
 
+    pub fn make_move(&mut self, mv: &GameMove) -> Result<(), MoveError> {
+        self.move_with_promotion(mv.from, mv.to, mv.promotion)
+    }
+
+    // all moves for the side to move that do not leave its own king in check
+    pub fn legal_moves(&self) -> Vec<GameMove> {
+        let mut moves: Vec<GameMove> = vec![];
+        match self.must_promote {
+            Promotion::MustPromote(_, _) => return moves,
+            Promotion::None => ()
+        }
+
+        for y in 0..self.size {
+            for x in 0..self.size {
+                if !self.coordinates_playable((x, y)) {
+                    continue;
+                }
+                match self.get_destinations((x, y)) {
+                    Destinations::Exists(d) => {
+                        for to in d {
+                            if self.is_promotion_move((x, y), to) {
+                                for new_piece_type in [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight] {
+                                    moves.push(GameMove { from: (x, y), to: to, promotion: Some(new_piece_type) });
+                                }
+                            } else {
+                                moves.push(GameMove { from: (x, y), to: to, promotion: None });
+                            }
+                        }
+                    },
+                    Destinations::None => (),
+                }
+            }
+        }
+
+        moves.retain(|mv| self.is_legal_move(mv));
+        moves
+    }
+
+    fn leaves_king_safe(&self, mv: &GameMove) -> bool {
+        let mut tmp_game = self.detached();
+        match tmp_game.make_move(mv) {
+            Ok(()) => !tmp_game.is_checked(self.turn),
+            Err(_) => false,
+        }
+    }
+
+    fn is_legal_move(&self, mv: &GameMove) -> bool {
+        match self.board[mv.from.1][mv.from.0] {
+            Content::Occupied(this_p) => {
+                // CASTLING: not out of or through check
+                let delta_x = mv.to.0 as i32 - mv.from.0 as i32;
+                if this_p.piece_type == PieceType::King && delta_x.abs() == 2 {
+                    if self.is_checked(this_p.color) {
+                        return false;
+                    }
+                    let passed = ((mv.from.0 as i32 + delta_x / 2) as usize, mv.from.1);
+                    let step = GameMove { from: mv.from, to: passed, promotion: None };
+                    if !self.leaves_king_safe(&step) {
+                        return false;
+                    }
+                }
+                self.leaves_king_safe(mv)
+            },
+            Content::Empty => false,
+        }
+    }
+
 }
 
 
 pub fn create_game() -> Game {
-    let size: usize = 8;
+    let size: usize = BOARD_SIZE;
     let mut game = Game {
         size: size,
-        board: vec![vec![Content::Empty; size]; size],
-        turn: Color::Black,
+        board: [[Content::Empty; BOARD_SIZE]; BOARD_SIZE],
+        turn: Color::White,
         w_king: (4, 7),
         b_king: (4, 0),
         w_check: false,
         b_check: false,
-        must_promote: Promotion::None
+        must_promote: Promotion::None,
+        hash: 0,
+        castle_rights: 0,
+        hash_history: vec![],
+        halfmove_clock: 0,
+        fullmove_number: 1,
+        events: None
     };
     game.reset();
     return game;
@@ -662,6 +893,13 @@ pub fn create_game() -> Game {
 
 
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GameMove {
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    pub promotion: Option<PieceType>,
+}
+
 #[derive(Debug)]
 pub enum Destinations {
     // Exists(Vec<Destination>),
@@ -695,6 +933,16 @@ pub enum Promotion {
     None
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MoveError {
+    NotYourTurn,
+    IllegalMove,
+    MustPromote,
+    MissingPromotion,
+    UnexpectedPromotion,
+    InvalidPromotion,
+}
+
 #[cfg(test)]
 mod tests {
     use core::panic;
@@ -850,6 +1098,65 @@ mod tests {
         game.move_from_to((1, 0), (1, 1));
     }
 
+    #[test]
+    fn move_with_promotion() {
+        let mut game = create_game();
+        game.move_from_to((0, 6), (0, 4));
+        game.move_from_to((1, 1), (1, 3));
+        game.move_from_to((0, 4), (1, 3));
+        game.move_from_to((0, 1), (0, 2));
+        game.move_from_to((1, 3), (1, 2));
+        game.move_from_to((0, 2), (0, 3));
+
+        assert_eq!(game.move_with_promotion((1, 2), (1, 1), Some(PieceType::Queen)), Err(MoveError::UnexpectedPromotion));
+        game.move_with_promotion((1, 2), (1, 1), None).unwrap();
+        game.move_from_to((0, 3), (0, 4));
+
+        assert!(game.is_promotion_move((1, 1), (2, 0)));
+        assert_eq!(game.move_with_promotion((1, 1), (2, 0), None), Err(MoveError::MissingPromotion));
+        assert_eq!(game.move_with_promotion((1, 1), (2, 0), Some(PieceType::King)), Err(MoveError::InvalidPromotion));
+        game.move_with_promotion((1, 1), (2, 0), Some(PieceType::Knight)).unwrap();
+
+        assert_eq!(game.get_turn(), Color::Black);
+        assert_eq!(game.get_content((2, 0)), Content::Occupied(Piece {
+            color: Color::White,
+            piece_type: PieceType::Knight,
+            times_moved: 5
+        }));
+        assert_eq!(game.move_with_promotion((2, 0), (3, 2), None), Err(MoveError::NotYourTurn));
+    }
+
+    #[test]
+    fn incremental_hash() {
+        let mut game = create_game();
+        let start_hash = game.hash();
+        assert_eq!(start_hash, game.compute_hash());
+
+        game.move_from_to((6, 7), (5, 5));
+        game.move_from_to((6, 0), (5, 2));
+        assert_ne!(game.hash(), start_hash);
+        game.move_from_to((5, 5), (6, 7));
+        game.move_from_to((5, 2), (6, 0));
+        assert_eq!(game.hash(), start_hash);
+        assert_eq!(game.repetition_count(), 1);
+
+        game.move_from_to((4, 6), (4, 4));
+        game.move_from_to((4, 1), (4, 3));
+        game.move_from_to((5, 7), (2, 4));
+        game.move_from_to((5, 0), (2, 3));
+        game.move_from_to((6, 7), (5, 5));
+        game.move_from_to((6, 0), (5, 2));
+        game.move_from_to((4, 7), (6, 7));
+        assert_eq!(game.hash(), game.compute_hash());
+        assert_eq!(game.repetition_count(), 0);
+    }
+
+    #[test]
+    fn game_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Game>();
+    }
+
     #[test]
     #[should_panic]
     fn promotion_wrong_piece_type() {
@@ -15,13 +15,48 @@ pub mod _App{
     use ggez::mint::Vector2;
 
     use INDA22PlusPlus_antmag_hw3::*;
+    use INDA22PlusPlus_antmag_hw3::bitbase::Bitbases;
+    use INDA22PlusPlus_antmag_hw3::events::{GameEvent, GameOverReason};
+    use INDA22PlusPlus_antmag_hw3::fen::START_FEN;
+    use INDA22PlusPlus_antmag_hw3::position::PositionBuilder;
+    use INDA22PlusPlus_antmag_hw3::puzzle::{Attempt, PuzzleSession, PuzzleTrainer};
+    use INDA22PlusPlus_antmag_hw3::pgn::PgnGame;
+    use INDA22PlusPlus_antmag_hw3::xboard::game_result;
+    use INDA22PlusPlus_antmag_hw3::analysis::{analyse_game, GameReport};
+    use INDA22PlusPlus_antmag_hw3::search::{SearchLimits, Searcher};
+    use std::io::Write;
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+
+    //Finished games are appended here, extract_puzzles can mine them for tactics
+    const GAMES_FILE : &str = "games.pgn";
+    //The post-game report as an annotated game
+    const ANALYSIS_FILE : &str = "analysis.pgn";
+    const ANALYSIS_DEPTH : u32 = 4;
+
+    //EDITOR LAYOUT, in screen coordinates like everything else
+    //The board is drawn smaller to leave room for the palette and the buttons
+    const EDITOR_BOARD : f32 = 0.75;
+    const PALETTE_X : f32 = 0.78;
+    const PALETTE_CELL : f32 = 0.1;
+    const PALETTE_PIECES : [PieceType; 6] = [PieceType::King, PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight, PieceType::Pawn];
+    const TEXT_SIZE : f32 = 28.0;
+    //The board size in pixels TEXT_SIZE was picked for, text grows and shrinks with the board
+    const TEXT_BOARD_SIZE : f32 = 1200.0;
+    const PANEL_LINE : f32 = 0.035;
+
+    //Shown from the promotion square towards the middle of the board
+    const PROMOTION_PIECES : [PieceType; 4] = [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight];
+
+    use ggez::event::{KeyCode, KeyMods};
     use Definitions::*;
 
     //Used to send a moves from application layer to network layer
     #[derive(Clone, Copy)]
     pub struct Move_channel{
         pub from : (usize, usize),
-        pub to : (usize, usize)
+        pub to : (usize, usize),
+        pub promotion : Option<PieceType>
     }
 
     pub struct Tex_resource{
@@ -56,7 +91,9 @@ pub mod _App{
     }
 
     pub struct Graphics_manager{
-        marks : Vec<(usize, usize)>
+        marks : Vec<(usize, usize)>,
+        //Black at the bottom, board cells and screen cells are then mirrored
+        flipped : bool
     }
     
     impl Graphics_manager{
@@ -76,8 +113,9 @@ pub mod _App{
             graphics::draw(_ctx, img, DP).expect("Failed!");
         }
 
-        pub fn render_piece(&self, _ctx : &mut Context, p : &Piece, pos : (usize, usize), RM : &Resource_manager){
-            let key : &str = match p.piece_type {
+        //The texture key of a piece, as given to save_tex
+        pub fn piece_key(p : &Piece) -> &'static str{
+            return match p.piece_type {
                 PieceType::Pawn => {
                     match p.color {
                         Definitions::Color::Black => "b_pawn",
@@ -120,6 +158,17 @@ pub mod _App{
                     }
                 }
             };
+        }
+
+        //Turns a board cell into the cell it is drawn on, and a clicked cell back into a board cell
+        pub fn view_cell(&self, cell : (usize, usize)) -> (usize, usize){
+            if(self.flipped) {return (7 - cell.0, 7 - cell.1);}
+            return cell;
+        }
+
+        pub fn render_piece(&self, _ctx : &mut Context, p : &Piece, pos : (usize, usize), RM : &Resource_manager){
+            let key : &str = Graphics_manager::piece_key(p);
+            let pos = self.view_cell(pos);
 
             self.render_img(
                 _ctx, key, RM, 
@@ -131,8 +180,9 @@ pub mod _App{
         pub fn render_markings(&self, _ctx : &mut Context, RM : &Resource_manager){
             //println!("Rendering {} markings!", self.marks.len());
             for i in 0..self.marks.len(){
+                let mark = self.view_cell(self.marks[i]);
                 self.render_img(_ctx, "cell_marking", RM, 
-                    ((self.marks[i].0 as f32)/8.0, (self.marks[i].1 as f32)/8.0),
+                    ((mark.0 as f32)/8.0, (mark.1 as f32)/8.0),
                     (1.0/8.0, 1.0/8.0)
                 );
             }
@@ -154,6 +204,22 @@ pub mod _App{
             }
         }
 
+        //Text is laid out in pixels, so it is scaled down to board units
+        pub fn render_text(&self, _ctx : &mut Context, text : &str, pos : (f32, f32), WS : &Window_settings){
+            let mut text = Text::new(text);
+            text.set_font(Font::default(), PxScale::from(TEXT_SIZE * WS.board.w / TEXT_BOARD_SIZE));
+            let DP = DrawParam::default()
+                .dest(Point2 { x: pos.0, y: pos.1 })
+                .scale(Vector2 { x: 1.0 / WS.board.w, y: 1.0 / WS.board.h })
+                .color(Color::BLACK);
+            graphics::draw(_ctx, &text, DP).expect("Failed!");
+        }
+
+        pub fn render_rect(&self, _ctx : &mut Context, rect : Rect, color : Color){
+            let mesh = Mesh::new_rectangle(_ctx, DrawMode::fill(), rect, color).expect("Could not build a rectangle!");
+            graphics::draw(_ctx, &mesh, DrawParam::default()).expect("Failed!");
+        }
+
         pub fn add_marking(&mut self, mark : (usize, usize)){
             self.marks.push(mark);
         }
@@ -164,9 +230,132 @@ pub mod _App{
 
     }
 
+    fn append_file(path : &str, text : &str){
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path);
+        match file {
+            Ok(mut file) => {
+                if let Err(e) = writeln!(file, "{}", text){
+                    println!("Could not write to {}: {}", path, e);
+                }
+            },
+            Err(e) => println!("Could not open {}: {}", path, e)
+        }
+    }
+
+    fn save_game(pgn_game : &PgnGame){
+        match pgn_game.to_pgn() {
+            Ok(text) => append_file(GAMES_FILE, &text),
+            Err(e) => println!("Could not write the game: {:?}", e)
+        }
+    }
+
+    #[derive(Eq, PartialEq, Clone, Copy)]
+    pub enum APP_MODE{
+        //Moves are handed to whoever owns the app
+        FREE = 0,
+        //Moves are checked against the current puzzle
+        PUZZLE = 1,
+        //The board editor is shown instead of the game
+        EDITOR = 2
+    }
+
+    //What a click outside the board does in the editor
+    #[derive(Clone, Copy, PartialEq)]
+    pub enum Editor_button{
+        //Picks the piece that clicks on the board place, None removes pieces
+        Palette(Option<Piece>),
+        Side,
+        Castling(Definitions::Color, bool),
+        Clear,
+        Reset,
+        FenField,
+        Import,
+        Export,
+        Start
+    }
+
+    pub struct Editor{
+        builder : PositionBuilder,
+        brush : Option<Piece>,
+        //Typed into with the keyboard, Enter or Import reads it
+        fen_input : String,
+        //Why the position can not be played, shown under the palette
+        status : String
+    }
+
+    //Every button of the editor and where it is, used both to draw and to find what was clicked
+    fn editor_buttons() -> Vec<(Rect, Editor_button)>{
+        let mut buttons = vec![];
+        for (row, piece_type) in PALETTE_PIECES.iter().enumerate(){
+            for (col, color) in [Definitions::Color::White, Definitions::Color::Black].iter().enumerate(){
+                let piece = Piece { color: *color, piece_type: *piece_type, times_moved: 0 };
+                let rect = Rect { x: PALETTE_X + col as f32 * PALETTE_CELL, y: row as f32 * PALETTE_CELL, w: PALETTE_CELL, h: PALETTE_CELL };
+                buttons.push((rect, Editor_button::Palette(Some(piece))));
+            }
+        }
+        buttons.push((Rect { x: PALETTE_X, y: 0.6, w: 2.0 * PALETTE_CELL, h: 0.05 }, Editor_button::Palette(None)));
+
+        buttons.push((Rect { x: 0.0, y: 0.77, w: 0.22, h: 0.06 }, Editor_button::Side));
+        let castling = [
+            (Definitions::Color::White, true), (Definitions::Color::White, false),
+            (Definitions::Color::Black, true), (Definitions::Color::Black, false)
+        ];
+        for (i, (color, kingside)) in castling.iter().enumerate(){
+            buttons.push((Rect { x: 0.24 + i as f32 * 0.09, y: 0.77, w: 0.08, h: 0.06 }, Editor_button::Castling(*color, *kingside)));
+        }
+        buttons.push((Rect { x: 0.62, y: 0.77, w: 0.17, h: 0.06 }, Editor_button::Clear));
+        buttons.push((Rect { x: 0.81, y: 0.77, w: 0.17, h: 0.06 }, Editor_button::Reset));
+        buttons.push((Rect { x: 0.0, y: 0.85, w: 0.98, h: 0.06 }, Editor_button::FenField));
+        buttons.push((Rect { x: 0.0, y: 0.93, w: 0.22, h: 0.06 }, Editor_button::Import));
+        buttons.push((Rect { x: 0.24, y: 0.93, w: 0.22, h: 0.06 }, Editor_button::Export));
+        buttons.push((Rect { x: 0.62, y: 0.93, w: 0.36, h: 0.06 }, Editor_button::Start));
+        return buttons;
+    }
+
+    //Where things are in the window, in pixels. The board is the biggest square that fits
+    //and is centred, the panels fill what is left on its sides
     pub struct Window_settings{
         pub width : f32,
-        pub height : f32
+        pub height : f32,
+        pub board : Rect,
+        pub panels : Vec<Rect>
+    }
+
+    impl Window_settings{
+        pub fn new(width : f32, height : f32) -> Window_settings{
+            let mut WS = Window_settings { width: 0.0, height: 0.0, board: Rect::zero(), panels: vec![] };
+            WS.layout(width, height);
+            return WS;
+        }
+
+        pub fn layout(&mut self, width : f32, height : f32){
+            let side = width.min(height);
+            self.width = width;
+            self.height = height;
+            self.board = Rect { x: (width - side) / 2.0, y: (height - side) / 2.0, w: side, h: side };
+            //Left and right of a wide window, above and below a tall one
+            let panels = if(width >= height) {
+                vec![Rect { x: 0.0, y: 0.0, w: self.board.x, h: height }, Rect { x: self.board.right(), y: 0.0, w: self.board.x, h: height }]
+            } else {
+                vec![Rect { x: 0.0, y: 0.0, w: width, h: self.board.y }, Rect { x: 0.0, y: self.board.bottom(), w: width, h: self.board.y }]
+            };
+            self.panels = panels.into_iter().filter(|r| r.w >= 1.0 && r.h >= 1.0).collect();
+        }
+
+        //Everything is drawn in board units, the board is the square from 0 to 1 wherever it is in the window
+        pub fn screen_coordinates(&self) -> Rect{
+            return Rect { x: -self.board.x / self.board.w, y: -self.board.y / self.board.h, w: self.width / self.board.w, h: self.height / self.board.h };
+        }
+
+        //A pixel in board units
+        pub fn to_board(&self, pos : (f32, f32)) -> (f32, f32){
+            return ((pos.0 - self.board.x) / self.board.w, (pos.1 - self.board.y) / self.board.h);
+        }
+
+        pub fn rect_to_board(&self, rect : Rect) -> Rect{
+            let (x, y) = self.to_board((rect.x, rect.y));
+            return Rect { x: x, y: y, w: rect.w / self.board.w, h: rect.h / self.board.h };
+        }
     }
 
     pub struct App{
@@ -176,46 +365,78 @@ pub mod _App{
 
         prev_click_pos : Option<(usize, usize)>,
         clicked_piece : bool,
-
-        game : Game
+        //A promotion waiting for the player to pick a piece
+        promotion_picker : Option<Move_channel>,
+        //Skips the picker, Q turns it on and off
+        auto_queen : bool,
+
+        game : Game,
+        //Every move made with make_move, saved when the game ends
+        history : Vec<GameMove>,
+        //Made on a thread once the game is over, so the window keeps drawing
+        report : Option<GameReport>,
+        report_channel : Option<Receiver<GameReport>>,
+        //Endgame tables for the hint key, made by the bitbase_gen binary
+        bitbases : Bitbases,
+
+        mode : APP_MODE,
+        trainer : Option<PuzzleTrainer>,
+        puzzle : Option<PuzzleSession>,
+        editor : Option<Editor>,
+        //Set when the game did not start from the usual position, saved games need it
+        start_fen : Option<String>
     }
 
     impl App{
         pub fn new(_ctx : &mut Context) -> App{
-            let WS : Window_settings = Window_settings{
-                width : 1200.0,
-                height : 1200.0
-            };
-            
             graphics::set_mode(_ctx, WindowMode{
-                width : WS.width,
-                height : WS.height,
+                width : 1200.0,
+                height : 1200.0,
                 maximized : false,
                 fullscreen_type : FullscreenType::Windowed,
                 borderless : false,
-                min_width : 0.0,
+                min_width : 400.0,
                 max_width : 0.0,
-                min_height : 0.0,
+                min_height : 400.0,
                 max_height : 0.0,
-                resizable : false,
+                resizable : true,
                 visible : true, 
-                resize_on_scale_factor_change : false
+                //Keeps the window the same size on the desktop when it moves to a screen with another scale factor
+                resize_on_scale_factor_change : true
             }).expect("Could not configure the window settings!");
-            set_screen_coordinates(_ctx, Rect { x: 0.0, y: 0.0, w: 1.0, h: 1.0 }).expect("Coudl not set ");
+            //The window size is in logical pixels, drawing and clicks are in physical ones
+            let (width, height) = graphics::drawable_size(_ctx);
+            let WS = Window_settings::new(width, height);
+            set_screen_coordinates(_ctx, WS.screen_coordinates()).expect("Coudl not set ");
 
             let mut new_app = App {  
 
                 RM : Resource_manager::new(_ctx),
                 WS : WS,
                 GM : Graphics_manager {
-                    marks : vec![]
+                    marks : vec![],
+                    flipped : false
                 },
                 prev_click_pos : None,
                 clicked_piece : false,
+                promotion_picker : None,
+                auto_queen : std::env::args().any(|arg| arg == "--auto-queen"),
 
                 game : create_game(),
+                history : vec![],
+                report : None,
+                report_channel : None,
+                bitbases : Bitbases::load_dir("bitbases").unwrap_or_default(),
+
+                mode : APP_MODE::FREE,
+                trainer : None,
+                puzzle : None,
+                editor : None,
+                start_fen : None
             };
 
+            //The game reports checks and the end of the game through its events
+            new_app.game.set_recording(true);
             new_app.save_textures(_ctx);
             return new_app;
         }
@@ -247,11 +468,79 @@ pub mod _App{
 
         pub fn render_frame(&self, _ctx : &mut Context) -> GameResult<()>{ 
             graphics::clear(_ctx, Color::WHITE);
+            if let Some(editor) = &self.editor{
+                self.render_editor(_ctx, editor);
+                return graphics::present(_ctx);
+            }
+            self.render_panels(_ctx);
             self.GM.render_board(_ctx, &self.game, &self.RM);
             self.GM.render_markings(_ctx, &self.RM);
+            if let Some(mc) = &self.promotion_picker{
+                self.render_promotion_picker(_ctx, mc);
+            }
             graphics::present(_ctx)
         }
 
+        //PANELS
+        //The first panel shows whose turn it is and the keys, the second one the moves
+        fn render_panels(&self, _ctx : &mut Context){
+            let turn = if(self.game.get_turn() == Definitions::Color::White) {"White to move"} else {"Black to move"};
+            let status = vec![
+                String::from(turn),
+                format!("Auto-queen {} (Q)", if(self.auto_queen) {"on"} else {"off"}),
+                String::from("F flips the board"),
+                String::from("H hint, T threats")
+            ];
+            let moves : Vec<String> = self.history.chunks(2)
+                .map(|pair| pair.iter().map(|mv| mv.to_uci()).collect::<Vec<String>>().join(" "))
+                .collect();
+
+            for (panel, lines) in self.WS.panels.iter().zip([status, moves].iter()){
+                let panel = self.WS.rect_to_board(*panel);
+                self.GM.render_rect(_ctx, panel, Color::new(0.92, 0.92, 0.92, 1.0));
+                //The newest moves when they do not all fit
+                let fits = ((panel.h - PANEL_LINE) / PANEL_LINE).max(0.0) as usize;
+                for (row, line) in lines.iter().skip(lines.len().saturating_sub(fits)).enumerate(){
+                    self.GM.render_text(_ctx, line, (panel.x + 0.01, panel.y + 0.01 + row as f32 * PANEL_LINE), &self.WS);
+                }
+            }
+        }
+
+        //WINDOW
+        //Called with the new size in physical pixels, also when the scale factor changes
+        pub fn resize_event(&mut self, _ctx : &mut Context, width : f32, height : f32){
+            self.WS.layout(width, height);
+            set_screen_coordinates(_ctx, self.WS.screen_coordinates()).expect("Could not set the screen coordinates!");
+        }
+
+        //PROMOTION
+        //The cells of the picker on the board, in the order of PROMOTION_PIECES
+        fn promotion_cells(&self, to : (usize, usize)) -> Vec<(usize, usize)>{
+            return (0..PROMOTION_PIECES.len()).map(|i| if(to.1 == 0) {(to.0, i)} else {(to.0, 7 - i)}).collect();
+        }
+
+        fn render_promotion_picker(&self, _ctx : &mut Context, mc : &Move_channel){
+            let color = match self.game.get_content(mc.from) {
+                Content::Occupied(p) => p.color,
+                Content::Empty => return
+            };
+            //Dims the board so the picker stands out
+            self.GM.render_rect(_ctx, Rect { x: 0.0, y: 0.0, w: 1.0, h: 1.0 }, Color::new(0.0, 0.0, 0.0, 0.4));
+            for (cell, piece_type) in self.promotion_cells(mc.to).into_iter().zip(PROMOTION_PIECES.iter()){
+                let view = self.GM.view_cell(cell);
+                let rect = Rect { x: view.0 as f32 / 8.0, y: view.1 as f32 / 8.0, w: 1.0 / 8.0, h: 1.0 / 8.0 };
+                self.GM.render_rect(_ctx, rect, Color::new(0.95, 0.95, 0.95, 1.0));
+                self.GM.render_piece(_ctx, &Piece { color: color, piece_type: *piece_type, times_moved: 0 }, cell, &self.RM);
+            }
+        }
+
+        //A click while the picker is open picks a piece or closes it
+        fn pick_promotion(&mut self, cell : (usize, usize)) -> Option<Move_channel>{
+            let mc = self.promotion_picker.take()?;
+            let picked = self.promotion_cells(mc.to).iter().position(|c| *c == cell);
+            return picked.map(|i| Move_channel { promotion: Some(PROMOTION_PIECES[i]), ..mc });
+        }
+
         pub fn set_clicked_piece(&mut self, val : bool){
             self.clicked_piece = val;
         }
@@ -270,19 +559,397 @@ pub mod _App{
             }
         }
 
-        pub fn make_move(&mut self, from : (usize, usize), to : (usize, usize)){
-            self.game.move_from_to(from, to);
+        pub fn make_move(&mut self, from : (usize, usize), to : (usize, usize), promotion : Option<PieceType>) -> Result<(), MoveError>{
+            self.game.move_with_promotion(from, to, promotion)?;
+            self.history.push(GameMove { from: from, to: to, promotion: promotion });
+            if let Some(result) = game_result(&self.game){
+                println!("Game over: {}", result);
+                let pgn_game = self.finished_game(result.split(' ').next().unwrap_or("*"));
+                save_game(&pgn_game);
+                self.start_report(pgn_game);
+            }
+            return Ok(());
+        }
+
+        pub fn get_history(&self) -> &Vec<GameMove>{
+            return &self.history;
+        }
+
+        fn finished_game(&self, result : &str) -> PgnGame{
+            let mut tags = vec![
+                (String::from("Event"), String::from("antmag-chess game")),
+                (String::from("Result"), String::from(result))
+            ];
+            if let Some(fen) = &self.start_fen{
+                tags.push((String::from("SetUp"), String::from("1")));
+                tags.push((String::from("FEN"), fen.clone()));
+            }
+            return PgnGame {
+                tags : tags,
+                moves : self.history.clone(),
+                result : String::from(result)
+            };
+        }
+
+        //REPORT
+        fn start_report(&mut self, pgn_game : PgnGame){
+            let (sender, receiver) = mpsc::channel();
+            self.report_channel = Some(receiver);
+            thread::spawn(move || {
+                let mut searcher = Searcher::new(SearchLimits::default());
+                let report = match analyse_game(&pgn_game, &mut searcher, SearchLimits::depth(ANALYSIS_DEPTH)) {
+                    Ok(report) => report,
+                    Err(e) => {
+                        println!("Could not analyse the game: {:?}", e);
+                        return;
+                    }
+                };
+                match report.to_pgn(&pgn_game) {
+                    Ok(text) => append_file(ANALYSIS_FILE, &text),
+                    Err(e) => println!("Could not write the analysis: {:?}", e)
+                }
+                sender.send(report).ok();
+            });
+        }
+
+        pub fn get_report(&self) -> Option<&GameReport>{
+            return self.report.as_ref();
         }
 
         pub fn is_valid_move(&mut self, from : (usize, usize), to : (usize, usize)) -> bool{
             return self.game.is_valid_move(from, to);
         }
 
+        pub fn get_game(&self) -> &Game{
+            return &self.game;
+        }
+
+        //Shows a move suggested by an engine
+        pub fn mark_move(&mut self, from : (usize, usize), to : (usize, usize)){
+            self.GM.marking_wipe();
+            self.GM.add_marking(from);
+            self.GM.add_marking(to);
+        }
+
+        //The player's own pieces go at the bottom, F turns the board by hand
+        pub fn set_flipped(&mut self, flipped : bool){
+            self.GM.flipped = flipped;
+        }
+
+        pub fn is_flipped(&self) -> bool{
+            return self.GM.flipped;
+        }
+
+        pub fn get_mode(&self) -> APP_MODE{
+            return self.mode;
+        }
+
+        //Starts a new game from any position, e.g. the one the server sent
+        pub fn set_position(&mut self, game : Game){
+            let fen = game.to_fen();
+            self.start_fen = if(fen == START_FEN) {None} else {Some(fen)};
+            self.game = game;
+            self.game.set_recording(true);
+            self.history.clear();
+            self.GM.marking_wipe();
+            self.prev_click_pos = None;
+        }
+
+        //EDITOR
+        pub fn start_editor(&mut self){
+            println!("Editing the position, click the palette to pick a piece and the board to place it");
+            let builder = PositionBuilder::from_game(&self.game);
+            self.editor = Some(Editor {
+                builder : builder,
+                brush : None,
+                fen_input : self.game.to_fen(),
+                status : String::new()
+            });
+            self.mode = APP_MODE::EDITOR;
+            self.GM.marking_wipe();
+            self.prev_click_pos = None;
+        }
+
+        fn render_editor(&self, _ctx : &mut Context, editor : &Editor){
+            let cell = EDITOR_BOARD / 8.0;
+            self.GM.render_img(_ctx, "chess_grid", &self.RM, (0.0, 0.0), (EDITOR_BOARD, EDITOR_BOARD));
+            for x in 0..8{
+                for y in 0..8{
+                    if let Some(p) = editor.builder.piece_at((x, y)){
+                        self.GM.render_img(_ctx, Graphics_manager::piece_key(&p), &self.RM, (x as f32 * cell, y as f32 * cell), (cell, cell));
+                    }
+                }
+            }
+
+            let grey = Color::new(0.85, 0.85, 0.85, 1.0);
+            let picked = Color::new(0.6, 0.85, 0.6, 1.0);
+            for (rect, button) in editor_buttons(){
+                let on = match button {
+                    Editor_button::Palette(p) => p.map(|p| (p.color, p.piece_type)) == editor.brush.map(|p| (p.color, p.piece_type)),
+                    Editor_button::Castling(color, kingside) => editor.builder.has_castling(color, kingside),
+                    _ => false
+                };
+                self.GM.render_rect(_ctx, rect, if(on) {picked} else {grey});
+                let label = match button {
+                    Editor_button::Palette(Some(p)) => {
+                        self.GM.render_img(_ctx, Graphics_manager::piece_key(&p), &self.RM, (rect.x, rect.y), (rect.w, rect.h));
+                        continue;
+                    },
+                    Editor_button::Palette(None) => String::from("Remove"),
+                    Editor_button::Side => {
+                        let side = if(editor.builder.turn() == Definitions::Color::White) {"White"} else {"Black"};
+                        format!("{} to move", side)
+                    },
+                    Editor_button::Castling(color, kingside) => {
+                        let c = if(kingside) {'K'} else {'Q'};
+                        let c = if(color == Definitions::Color::White) {c} else {c.to_ascii_lowercase()};
+                        c.to_string()
+                    },
+                    Editor_button::Clear => String::from("Clear"),
+                    Editor_button::Reset => String::from("Start pos"),
+                    Editor_button::FenField => format!("{}_", editor.fen_input),
+                    Editor_button::Import => String::from("Import FEN"),
+                    Editor_button::Export => String::from("Export FEN"),
+                    Editor_button::Start => String::from("Start game from here")
+                };
+                self.GM.render_text(_ctx, &label, (rect.x + 0.01, rect.y + 0.015), &self.WS);
+            }
+            self.GM.render_text(_ctx, &editor.status, (PALETTE_X, 0.67), &self.WS);
+        }
+
+        fn editor_click(&mut self, button : ggez::event::MouseButton, pos : (f32, f32)){
+            let editor = match self.editor.as_mut() {
+                Some(editor) => editor,
+                None => return
+            };
+            if(pos.0 >= 0.0 && pos.1 >= 0.0 && pos.0 < EDITOR_BOARD && pos.1 < EDITOR_BOARD){
+                let cell = ((pos.0 / EDITOR_BOARD * 8.0) as usize, (pos.1 / EDITOR_BOARD * 8.0) as usize);
+                let here = editor.builder.piece_at(cell).map(|p| (p.color, p.piece_type));
+                //A right click or a click with the same piece empties the square
+                match editor.brush {
+                    Some(p) if button != ggez::event::MouseButton::Right && here != Some((p.color, p.piece_type)) => {
+                        editor.builder.place(cell, p.color, p.piece_type);
+                    },
+                    _ => {
+                        editor.builder.remove(cell);
+                    }
+                }
+                self.editor_status();
+                return;
+            }
+
+            let clicked = editor_buttons().into_iter().find(|(rect, _)| rect.contains(Point2 { x: pos.0, y: pos.1 }));
+            match clicked.map(|(_, button)| button) {
+                Some(Editor_button::Palette(p)) => editor.brush = p,
+                Some(Editor_button::Side) => {
+                    let other = if(editor.builder.turn() == Definitions::Color::White) {Definitions::Color::Black} else {Definitions::Color::White};
+                    editor.builder.side_to_move(other);
+                },
+                Some(Editor_button::Castling(color, kingside)) => {
+                    let allowed = editor.builder.has_castling(color, kingside);
+                    editor.builder.castling(color, kingside, !allowed);
+                },
+                Some(Editor_button::Clear) => {
+                    editor.builder.clear();
+                },
+                Some(Editor_button::Reset) => editor.builder = PositionBuilder::from_game(&create_game()),
+                Some(Editor_button::Import) => self.editor_import(),
+                Some(Editor_button::Export) => self.editor_export(),
+                Some(Editor_button::Start) => self.editor_start(),
+                Some(Editor_button::FenField) | None => {}
+            }
+            self.editor_status();
+        }
+
+        fn editor_status(&mut self){
+            if let Some(editor) = self.editor.as_mut(){
+                editor.status = match editor.builder.build() {
+                    Ok(_) => String::new(),
+                    Err(e) => format!("{:?}", e)
+                };
+            }
+        }
+
+        fn editor_import(&mut self){
+            if let Some(editor) = self.editor.as_mut(){
+                match Game::from_fen(editor.fen_input.trim()) {
+                    Ok(game) => editor.builder = PositionBuilder::from_game(&game),
+                    Err(e) => println!("Could not read the FEN: {:?}", e)
+                }
+            }
+        }
+
+        fn editor_export(&mut self){
+            if let Some(editor) = self.editor.as_mut(){
+                match editor.builder.build() {
+                    Ok(game) => {
+                        editor.fen_input = game.to_fen();
+                        println!("{}", editor.fen_input);
+                    },
+                    Err(e) => println!("Only a legal position can be exported: {:?}", e)
+                }
+            }
+        }
+
+        fn editor_start(&mut self){
+            let built = match &self.editor {
+                Some(editor) => editor.builder.build(),
+                None => return
+            };
+            match built {
+                Ok(game) => {
+                    println!("Starting from {}", game.to_fen());
+                    self.editor = None;
+                    self.mode = APP_MODE::FREE;
+                    self.set_position(game);
+                },
+                Err(e) => println!("This position can not be played: {:?}", e)
+            }
+        }
+
+        //PUZZLES
+        pub fn start_puzzles(&mut self, trainer : PuzzleTrainer){
+            println!("Loaded {} puzzles, N skips to the next one and H shows a hint", trainer.len());
+            self.mode = APP_MODE::PUZZLE;
+            self.trainer = Some(trainer);
+            self.next_puzzle();
+        }
+
+        pub fn next_puzzle(&mut self){
+            let trainer = match self.trainer.as_mut() {
+                Some(trainer) => trainer,
+                None => return
+            };
+            //Skipping an unsolved puzzle counts as failing it
+            if let Some(session) = &self.puzzle{
+                if(session.is_finished() == false){
+                    trainer.record(session.puzzle(), false);
+                }
+            }
+            self.GM.marking_wipe();
+            self.prev_click_pos = None;
+            self.puzzle = trainer.next_puzzle();
+            match &self.puzzle {
+                Some(session) => {
+                    let to_move = if(session.game().get_turn() == Definitions::Color::White) {"White"} else {"Black"};
+                    println!("Puzzle {} (rating {}), {} to move", session.puzzle().id, session.puzzle().rating, to_move);
+                    self.GM.flipped = session.game().get_turn() == Definitions::Color::Black;
+                    self.game = session.game().clone();
+                },
+                None => {
+                    println!("No puzzles left! Rating {}, solved {}, failed {}, best streak {}",
+                        trainer.rating, trainer.solved, trainer.failed, trainer.best_streak);
+                    self.mode = APP_MODE::FREE;
+                }
+            }
+        }
+
+        fn puzzle_move(&mut self, mc : &Move_channel){
+            let session = match self.puzzle.as_mut() {
+                Some(session) => session,
+                None => return
+            };
+            let attempt = session.try_move(&GameMove { from: mc.from, to: mc.to, promotion: mc.promotion });
+            let trainer = self.trainer.as_mut().expect("Puzzles are only played with a trainer!");
+            match attempt {
+                Attempt::Correct { reply } => {
+                    self.game = session.game().clone();
+                    self.mark_move(reply.from, reply.to);
+                },
+                Attempt::Solved => {
+                    self.game = session.game().clone();
+                    let change = trainer.record(session.puzzle(), true);
+                    println!("Solved! Rating {} ({:+}), streak {}, press N for the next puzzle", trainer.rating, change, trainer.streak);
+                },
+                Attempt::Wrong => {
+                    let change = trainer.record(session.puzzle(), false);
+                    let solution : Vec<String> = session.puzzle().solution.iter().map(|mv| mv.to_uci()).collect();
+                    println!("Wrong! The solution was {}. Rating {} ({:+}), press N for the next puzzle", solution.join(" "), trainer.rating, change);
+                },
+                Attempt::Over => println!("The puzzle is over, press N for the next one")
+            }
+        }
+
+        //Tells whether each capture the selected piece can make wins or loses material
+        fn capture_hints(&self, from : (usize, usize)){
+            for mv in self.game.legal_moves(){
+                if(mv.from != from || mv.promotion.map_or(false, |p| p != PieceType::Queen)){
+                    continue;
+                }
+                if let Content::Occupied(_) = self.game.get_content(mv.to){
+                    let see = self.game.see(&mv);
+                    if(see >= 0) {println!("Taking on {:?} is safe ({:+})", mv.to, see);}
+                    else {println!("Taking on {:?} loses material ({:+})", mv.to, see);}
+                }
+            }
+        }
+
+        //Marks every hanging piece, pinned piece and checker of the side to move
+        pub fn show_threats(&mut self){
+            let turn = self.game.get_turn();
+            self.GM.marking_wipe();
+            for hanging in self.game.hanging_pieces(turn){
+                println!("Hanging {:?} on {:?}, attacked by {:?}", hanging.piece.piece_type, hanging.square, hanging.attackers);
+                self.GM.add_marking(hanging.square);
+            }
+            for pin in self.game.pins(turn){
+                println!("Pinned piece on {:?} by {:?}", pin.pinned, pin.pinner);
+                self.GM.add_marking(pin.pinned);
+            }
+            for checker in self.game.checkers(){
+                self.GM.add_marking(checker);
+            }
+        }
+
+        //Marks the perfect move when the position is in a loaded bitbase
+        pub fn hint(&mut self){
+            if(self.mode == APP_MODE::PUZZLE){
+                if let Some(mv) = self.puzzle.as_ref().and_then(|session| session.hint()){
+                    self.mark_move(mv.from, mv.to);
+                }
+                return;
+            }
+            match self.bitbases.best_move(&self.game) {
+                Some(mv) => {
+                    if let Some(probe) = self.bitbases.probe(&self.game){
+                        println!("Bitbase: {:?}, {:?} plies to mate", probe.wdl, probe.plies_to_mate);
+                    }
+                    self.mark_move(mv.from, mv.to);
+                },
+                None => println!("No bitbase for this position")
+            }
+        }
+
     }
 
     impl /*EventHandler for*/ App{
-        fn update(&mut self, _ctx : &mut Context) -> GameResult<()>{
-            //println!("Updated!");
+        pub fn update(&mut self, _ctx : &mut Context) -> GameResult<()>{
+            //Picks up the post-game report when the analysis thread is done
+            let received = match &self.report_channel {
+                Some(channel) => channel.try_recv().ok(),
+                None => None
+            };
+            if let Some(report) = received{
+                for analysis in report.moves.iter(){
+                    if let (Some(nag), Some(best)) = (analysis.class.nag(), &analysis.best_san){
+                        println!("{} {} {:?}, {} was best", analysis.san, nag, analysis.class, best);
+                    }
+                }
+                println!("{}", report.summary());
+                println!("The annotated game was saved to {}", ANALYSIS_FILE);
+                self.report = Some(report);
+                self.report_channel = None;
+            }
+
+            for event in self.game.drain_events(){
+                match event {
+                    GameEvent::Check { color } => println!("{:?} is in check!", color),
+                    GameEvent::PromotionPending { .. } => println!("Choose a piece to promote to"),
+                    GameEvent::GameOver(GameOverReason::Checkmate { winner }) => println!("Checkmate, {:?} wins", winner),
+                    GameEvent::GameOver(reason) => println!("Draw by {:?}", reason),
+                    _ => ()
+                }
+            }
             Ok(())
         }
 
@@ -293,15 +960,30 @@ pub mod _App{
         }
 
         pub fn mouse_button_down_event(&mut self, _ctx: &mut Context, _button: ggez::event::MouseButton, _x: f32, _y: f32) -> Option<Move_channel>{
+            let pos = self.WS.to_board((_x, _y));
+            if(self.mode == APP_MODE::EDITOR){
+                self.editor_click(_button, pos);
+                return None;
+            }
             println!("Clicked x: {}, y: {}", _x,_y);
-            let cell : (usize, usize) = ((_x / self.WS.width * 8.0).floor() as usize , (_y / self.WS.height * 8.0).floor() as usize);
+            //The panels next to the board are not clickable
+            if(pos.0 < 0.0 || pos.0 >= 1.0 || pos.1 < 0.0 || pos.1 >= 1.0){
+                return None;
+            }
+            let cell : (usize, usize) = ((pos.0 * 8.0).floor() as usize , (pos.1 * 8.0).floor() as usize);
+            let cell = self.GM.view_cell(cell);
             println!("{}, {}", cell.0, cell.1);
 
             let is_piece = self.game.coordinates_playable((cell.0, cell.1));
             let mut return_val = None;
 
-            if let None = self.prev_click_pos {
-                if(is_piece) {self.check(cell, (0,0), true);}
+            if(self.promotion_picker.is_some()){
+                return_val = self.pick_promotion(cell);
+            } else if let None = self.prev_click_pos {
+                if(is_piece) {
+                    self.check(cell, (0,0), true);
+                    self.capture_hints(cell);
+                }
                 self.prev_click_pos = Some(cell);
             } else{
                 let prev_pos = self.prev_click_pos.unwrap();
@@ -309,15 +991,75 @@ pub mod _App{
 
                 if(self.check(prev_pos, cell, false)) {
                     println!("Moving a piece!");
-                    return_val = Some(Move_channel { from: prev_pos, to: cell})
+                    let mc = Move_channel { from: prev_pos, to: cell, promotion: None };
+                    if(self.game.is_promotion_move(prev_pos, cell) == false){
+                        return_val = Some(mc);
+                    } else if(self.auto_queen){
+                        return_val = Some(Move_channel { promotion: Some(PieceType::Queen), ..mc });
+                    } else {
+                        //The move is sent once a piece is picked
+                        self.promotion_picker = Some(mc);
+                        self.prev_click_pos = None;
+                    }
                     //self.game.move_from_to(prev_pos, cell);
                 }else{
                     self.prev_click_pos = None;
                 }
                 self.GM.marking_wipe();
             }
+            //Puzzle moves never leave the app
+            if(self.mode == APP_MODE::PUZZLE){
+                if let Some(mc) = return_val{
+                    self.puzzle_move(&mc);
+                    self.prev_click_pos = None;
+                }
+                return None;
+            }
             return return_val;
         }
+
+        pub fn key_down_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods, _repeat: bool){
+            //Letters go to the FEN field while editing
+            if let Some(editor) = self.editor.as_mut(){
+                match keycode {
+                    KeyCode::Back => {editor.fen_input.pop();},
+                    KeyCode::Return => {
+                        self.editor_import();
+                        self.editor_status();
+                    },
+                    KeyCode::Escape => event::quit(_ctx),
+                    _ => {}
+                }
+                return;
+            }
+            match keycode {
+                KeyCode::H => self.hint(),
+                KeyCode::N => self.next_puzzle(),
+                KeyCode::T => self.show_threats(),
+                KeyCode::F => {
+                    let flipped = self.is_flipped();
+                    self.set_flipped(!flipped);
+                },
+                KeyCode::Q => {
+                    self.auto_queen = !self.auto_queen;
+                    println!("Auto-queen {}", if(self.auto_queen) {"on"} else {"off"});
+                },
+                //Closes the promotion picker before it quits
+                KeyCode::Escape if self.promotion_picker.is_some() => self.promotion_picker = None,
+                //Overriding the handler drops ggez's default of quitting on escape
+                KeyCode::Escape => event::quit(_ctx),
+                _ => {}
+            }
+        }
+
+        pub fn text_input_event(&mut self, _ctx: &mut Context, character: char){
+            if let Some(editor) = self.editor.as_mut(){
+                //Backspace and Enter come as characters too, key_down_event handles them
+                if(character.is_ascii_graphic() || character == ' '){
+                    editor.fen_input.push(character);
+                }
+            }
+        }
     }
 
 }
@@ -332,6 +1074,9 @@ pub mod Net_app{
 
     use crate::{Net_app::c2s_message::Msg::ConnectRequest, _App};
     use INDA22PlusPlus_antmag_hw3::Game;
+    use INDA22PlusPlus_antmag_hw3::Definitions::Color;
+    use INDA22PlusPlus_antmag_hw3::GameMove;
+    use INDA22PlusPlus_antmag_hw3::netcode::{move_from_net, move_to_net};
     use prost::Message;
 
     use std::io::Cursor;
@@ -388,9 +1133,15 @@ pub mod Net_app{
                     _ => panic!("Unknown command: {}", host_or_client)
                 }
             };
+
+            //--server --edit lets the host set up the position before answering the client
+            let mut app = App::new(ctx);
+            if(typ == NET_TYPE::SERVER && std::env::args().any(|arg| arg == "--edit")){
+                app.start_editor();
+            }
             
             return Net_app{
-                app : App::new(ctx),
+                app : app,
 
                 fen : String::new(),
                 state : if(typ == NET_TYPE::SERVER){SERVER_INIT_STATE} else {NET_STATE::NOT_ESTABLISHED}, 
@@ -428,7 +1179,13 @@ pub mod Net_app{
             self.established_connection = true;
             self.state = NET_STATE::WAITING;
 
-            if(packet.client_is_white() == true){
+            if let Some(position) = &packet.starting_position{
+                let game = Game::from_fen(&position.fen_string).expect("The server sent a position we can not read!");
+                self.app.set_position(game);
+            }
+            let my_color = if(packet.client_is_white() == true) {Color::White} else {Color::Black};
+            self.app.set_flipped(my_color == Color::Black);
+            if(self.app.get_game().get_turn() == my_color){
                 self.state = NET_STATE::MY_TURN;
             }
         }
@@ -439,7 +1196,7 @@ pub mod Net_app{
             if(packet.legal == true){
                 println!("My move was legal!");
                 if let Some(mv_channel) = self.mv_cache{
-                    self.app.make_move(mv_channel.from, mv_channel.to);
+                    self.app.make_move(mv_channel.from, mv_channel.to, mv_channel.promotion).expect("The server accepted a move we can not make!");
                     self.state = NET_STATE::WAITING;
                 }else {
                     panic!("Could not complete a move as there was no chached available");
@@ -517,15 +1274,21 @@ pub mod Net_app{
             res.client_is_white = Some(SERVER_INIT_STATE == NET_STATE::WAITING);
             res.game_id = Some(0);
             res.success = false;
+            //Whatever the host set up in the editor, the start position otherwise
             res.starting_position = Some(
                 BoardState { 
-                    fen_string: String::from_str("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap()
+                    fen_string: self.app.get_game().to_fen()
                 }
             );
 
             if(self.established_connection == false){
                 res.success = true;
                 self.established_connection = true;
+                self.app.set_flipped(res.client_is_white());
+                //The server plays black, so it starts when black is to move
+                if(self.app.get_game().get_turn() == Color::Black){
+                    self.state = NET_STATE::MY_TURN;
+                }
             }
 
             let msg = Msg::ConnectAck(res);
@@ -539,19 +1302,21 @@ pub mod Net_app{
 
         fn on_Move_c2s(&mut self, mv: net_packet::Move){
             assert!(self.typ == NET_TYPE::SERVER, "What!!!???");
-            let from_pos = ((mv.from_square%8) as usize, (mv.from_square/8) as usize);
-            let to_pos = ((mv.to_square%8) as usize, (mv.to_square/8) as usize);
+            let GameMove { from: from_pos, to: to_pos, promotion } = move_from_net(&mv);
             
             println!("Recieved move request:
                 from row: {}, col: {}
                 to row: {}, col: {}", 
             from_pos.1, from_pos.0, to_pos.1, to_pos.0);
-            if(self.app.is_valid_move(from_pos, to_pos) == false){
-                panic!("Server does not think this move allowed!");
-            }else{
-                self.app.make_move(from_pos, to_pos);
-                self.state = NET_STATE::MY_TURN;
-                self.on_Move_c2s_response(true);
+            match self.app.make_move(from_pos, to_pos, promotion) {
+                Ok(()) => {
+                    self.state = NET_STATE::MY_TURN;
+                    self.on_Move_c2s_response(true);
+                },
+                Err(e) => {
+                    println!("Server does not think this move allowed: {:?}", e);
+                    self.on_Move_c2s_response(false);
+                }
             }
         }
 
@@ -569,27 +1334,21 @@ pub mod Net_app{
         }
 
         fn on_Move_s2c(&mut self, mv: net_packet::Move){
-            let from_pos = ((mv.from_square%8) as usize, (mv.from_square/8) as usize);
-            let to_pos = ((mv.to_square%8) as usize, (mv.to_square/8) as usize);
+            let GameMove { from: from_pos, to: to_pos, promotion } = move_from_net(&mv);
             println!("Recieved move request:
                 from row: {}, col: {}
                 to row: {}, col: {}", 
             from_pos.1, from_pos.0, to_pos.1, to_pos.0);
 
-            if(self.app.is_valid_move(from_pos, to_pos) == false){
-                panic!("Client does not think this is a valid move!");
+            if let Err(e) = self.app.make_move(from_pos, to_pos, promotion){
+                panic!("Client does not think this is a valid move: {:?}", e);
             }else{
-                self.app.make_move(from_pos, to_pos);
                 self.state = NET_STATE::MY_TURN;
             }
         }
 
         fn Move_client_request(&mut self, mv : &Move_channel){
-            let mv_formatted = net_packet::Move {
-                from_square: (mv.from.0 + mv.from.1 * 8) as u32, //xy
-                to_square : (mv.to.0 + mv.to.1 * 8) as u32,
-                promotion : None
-            };
+            let mv_formatted = move_to_net(&GameMove { from: mv.from, to: mv.to, promotion: mv.promotion });
             println!("Client making a move request");
             self.mv_cache = Some(*mv);
             self.state = NET_STATE::WAITING; //This will make the client listen to the server's response
@@ -605,13 +1364,9 @@ pub mod Net_app{
         fn Move_server_request(&mut self, mv : &Move_channel){
             //The server is assumed to be correct, it does not need client validation
             self.state = NET_STATE::WAITING;
-            self.app.make_move(mv.from, mv.to);
+            self.app.make_move(mv.from, mv.to, mv.promotion).expect("The server tried to make an illegal move!");
 
-            let mv_formatted = net_packet::Move {
-                from_square: (mv.from.0 + mv.from.1 * 8) as u32, //xy
-                to_square : (mv.to.0 + mv.to.1 * 8) as u32,
-                promotion : None
-            };
+            let mv_formatted = move_to_net(&GameMove { from: mv.from, to: mv.to, promotion: mv.promotion });
             println!("Server making a move request!");
             self.mv_cache = Some(*mv);
             let mv_msg = s2c_message::Msg::Move(mv_formatted);
@@ -632,10 +1387,13 @@ pub mod Net_app{
     impl EventHandler for Net_app{
         fn update(&mut self, _ctx : &mut Context) -> GameResult<()>{
             //println!("Updated!");
+            self.app.update(_ctx)?;
             
             //self.app.render_frame(_ctx).expect("Something wierd happened!");
 
-            if(self.state == NET_STATE::WAITING || self.established_connection == false){
+            //The client's connect request waits in the socket until the host is done editing
+            let editing = self.app.get_mode() == APP_MODE::EDITOR;
+            if((self.state == NET_STATE::WAITING || self.established_connection == false) && editing == false){
                 if(self.get_net_type() == NET_TYPE::SERVER){
                     self.check_connection_server();
                 }else{
@@ -662,6 +1420,18 @@ pub mod Net_app{
 
         }
 
+        fn key_down_event(&mut self, _ctx: &mut Context, keycode: ggez::event::KeyCode, keymods: ggez::event::KeyMods, repeat: bool) {
+            self.app.key_down_event(_ctx, keycode, keymods, repeat);
+        }
+
+        fn resize_event(&mut self, _ctx: &mut Context, width: f32, height: f32) {
+            self.app.resize_event(_ctx, width, height);
+        }
+
+        fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+            self.app.text_input_event(_ctx, character);
+        }
+
     }
 
     /* 
@@ -679,6 +1449,267 @@ pub mod Net_app{
 }
 
 
+pub mod Engine_app{
+    use std::time::Duration;
+    use std::sync::{Arc, Mutex};
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+    use ggez::{Context, GameResult, event::EventHandler};
+    use super::_App::*;
+
+    use INDA22PlusPlus_antmag_hw3::*;
+    use INDA22PlusPlus_antmag_hw3::external_engine::{ExternalEngine, EngineError, EngineOutput, EngineScore};
+    use INDA22PlusPlus_antmag_hw3::xboard::game_result;
+    use INDA22PlusPlus_antmag_hw3::search::SearchLimits;
+    use INDA22PlusPlus_antmag_hw3::polyglot::{Book, BookRng};
+
+    const ENGINE_MOVETIME : Duration = Duration::from_millis(1000);
+
+    #[derive(Eq, PartialEq, Clone, Copy)]
+    pub enum ENGINE_MODE{
+        //The engine plays black
+        PLAY = 0,
+        //Both sides are played by hand and the engine marks its best move
+        ANALYSE = 1
+    }
+
+    pub struct Engine_app{
+        app : App,
+        //Searches run on a thread so the window keeps drawing, the thread borrows the engine
+        engine : Arc<Mutex<ExternalEngine>>,
+        thinking : Option<Receiver<Result<EngineOutput, EngineError>>>,
+        mode : ENGINE_MODE,
+        //Opening moves are taken from the book while it has any
+        book : Option<Book>,
+        rng : BookRng,
+        //The analysis is only run once per position
+        analysed : bool
+    }
+
+    impl Engine_app{
+        pub fn new(ctx : &mut Context, path : &str, mode : ENGINE_MODE, book : Option<Book>) -> Self{
+            let mut engine = ExternalEngine::spawn(path, &[]).expect("Could not start the engine!");
+            engine.new_game().expect("The engine did not answer isready!");
+            println!("Started engine {}", engine.name.clone().unwrap_or(String::from(path)));
+
+            return Engine_app{
+                app : App::new(ctx),
+                engine : Arc::new(Mutex::new(engine)),
+                thinking : None,
+                mode : mode,
+                book : book,
+                rng : BookRng::from_time(),
+                analysed : false
+            }
+        }
+
+        //Starts a search of the current position, the answer is picked up by update
+        fn start_search(&mut self){
+            let engine = Arc::clone(&self.engine);
+            let game = self.app.get_game().detached();
+            let (sender, receiver) = mpsc::channel();
+            self.thinking = Some(receiver);
+            thread::spawn(move || {
+                let limits = SearchLimits::movetime(ENGINE_MOVETIME);
+                let output = engine.lock().expect("The engine thread panicked!").analyse(&game, &limits);
+                sender.send(output).ok();
+            });
+        }
+
+        fn engine_move(&mut self){
+            if let Some(book) = &self.book{
+                if let Some(mv) = book.pick(self.app.get_game(), &mut self.rng){
+                    println!("Book move: {}", mv.to_uci());
+                    self.app.make_move(mv.from, mv.to, mv.promotion).expect("Book moves are checked to be legal!");
+                    return;
+                }
+            }
+            self.start_search();
+        }
+
+        fn on_engine_output(&mut self, output : EngineOutput){
+            match self.mode {
+                ENGINE_MODE::PLAY => {
+                    match output.best_move {
+                        Some(mv) => self.app.make_move(mv.from, mv.to, mv.promotion).expect("The engine move was checked to be legal!"),
+                        None => println!("The engine has no moves left")
+                    }
+                },
+                ENGINE_MODE::ANALYSE => {
+                    match output.last_score() {
+                        Some(EngineScore::Centipawns(cp)) => println!("Engine score: {}", cp),
+                        Some(EngineScore::Mate(n)) => println!("Engine score: mate in {}", n),
+                        None => {}
+                    }
+                    if let Some(mv) = output.best_move{
+                        println!("Engine best move: {}", mv.to_uci());
+                        self.app.mark_move(mv.from, mv.to);
+                    }
+                }
+            }
+        }
+    }
+
+    impl EventHandler for Engine_app{
+        fn update(&mut self, _ctx : &mut Context) -> GameResult<()>{
+            self.app.update(_ctx)?;
+            if let Some(receiver) = &self.thinking{
+                match receiver.try_recv() {
+                    Ok(Ok(output)) => self.on_engine_output(output),
+                    Ok(Err(e)) => panic!("The engine failed: {:?}", e),
+                    Err(mpsc::TryRecvError::Empty) => return Ok(()),
+                    Err(mpsc::TryRecvError::Disconnected) => panic!("The engine thread stopped!")
+                }
+                self.thinking = None;
+            }
+            //Nothing to search once the game is over
+            if(game_result(self.app.get_game()).is_some()){
+                return Ok(());
+            }
+            match self.mode {
+                ENGINE_MODE::PLAY => {
+                    if(self.app.get_game().get_turn() == Definitions::Color::Black){
+                        self.engine_move();
+                    }
+                },
+                ENGINE_MODE::ANALYSE => {
+                    if(self.analysed == false){
+                        self.start_search();
+                        self.analysed = true;
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        fn draw(&mut self, _ctx : &mut Context) -> GameResult<()>{
+            return self.app.render_frame(_ctx);
+        }
+
+        fn mouse_button_down_event(&mut self, _ctx: &mut Context, _button: ggez::event::MouseButton, _x: f32, _y: f32) {
+            if(self.mode == ENGINE_MODE::PLAY && self.app.get_game().get_turn() == Definitions::Color::Black){
+                return;
+            }
+            //The analysis is of the position on the board, so it has to finish first
+            if(self.thinking.is_some()){
+                return;
+            }
+            let res = self.app.mouse_button_down_event(_ctx, _button, _x, _y);
+            if let Some(mc) = res{
+                if let Err(e) = self.app.make_move(mc.from, mc.to, mc.promotion){
+                    println!("Could not make the move: {:?}", e);
+                }
+                self.analysed = false;
+            }
+        }
+
+        fn key_down_event(&mut self, _ctx: &mut Context, keycode: ggez::event::KeyCode, keymods: ggez::event::KeyMods, repeat: bool) {
+            self.app.key_down_event(_ctx, keycode, keymods, repeat);
+        }
+
+        fn resize_event(&mut self, _ctx: &mut Context, width: f32, height: f32) {
+            self.app.resize_event(_ctx, width, height);
+        }
+    }
+}
+
+pub mod Puzzle_app{
+    use ggez::{Context, GameResult, event::EventHandler};
+    use super::_App::*;
+
+    use INDA22PlusPlus_antmag_hw3::puzzle::{load_puzzles, PuzzleTrainer};
+
+    //Runs the app on its own in puzzle mode
+    pub struct Puzzle_app{
+        app : App
+    }
+
+    impl Puzzle_app{
+        pub fn new(ctx : &mut Context, path : &str) -> Self{
+            let puzzles = load_puzzles(path).expect("Could not read the puzzles!");
+            let mut app = App::new(ctx);
+            app.start_puzzles(PuzzleTrainer::new(puzzles));
+            return Puzzle_app{
+                app : app
+            }
+        }
+    }
+
+    impl EventHandler for Puzzle_app{
+        fn update(&mut self, _ctx : &mut Context) -> GameResult<()>{
+            return self.app.update(_ctx);
+        }
+
+        fn draw(&mut self, _ctx : &mut Context) -> GameResult<()>{
+            return self.app.render_frame(_ctx);
+        }
+
+        fn mouse_button_down_event(&mut self, _ctx: &mut Context, _button: ggez::event::MouseButton, _x: f32, _y: f32) {
+            self.app.mouse_button_down_event(_ctx, _button, _x, _y);
+        }
+
+        fn key_down_event(&mut self, _ctx: &mut Context, keycode: ggez::event::KeyCode, keymods: ggez::event::KeyMods, repeat: bool) {
+            self.app.key_down_event(_ctx, keycode, keymods, repeat);
+        }
+
+        fn resize_event(&mut self, _ctx: &mut Context, width: f32, height: f32) {
+            self.app.resize_event(_ctx, width, height);
+        }
+    }
+}
+
+pub mod Editor_app{
+    use ggez::{Context, GameResult, event::EventHandler};
+    use super::_App::*;
+
+    //Runs the board editor, the game started from it is played by hand on one board
+    pub struct Editor_app{
+        app : App
+    }
+
+    impl Editor_app{
+        pub fn new(ctx : &mut Context) -> Self{
+            let mut app = App::new(ctx);
+            app.start_editor();
+            return Editor_app{
+                app : app
+            }
+        }
+    }
+
+    impl EventHandler for Editor_app{
+        fn update(&mut self, _ctx : &mut Context) -> GameResult<()>{
+            return self.app.update(_ctx);
+        }
+
+        fn draw(&mut self, _ctx : &mut Context) -> GameResult<()>{
+            return self.app.render_frame(_ctx);
+        }
+
+        fn mouse_button_down_event(&mut self, _ctx: &mut Context, _button: ggez::event::MouseButton, _x: f32, _y: f32) {
+            let res = self.app.mouse_button_down_event(_ctx, _button, _x, _y);
+            if let Some(mc) = res{
+                if let Err(e) = self.app.make_move(mc.from, mc.to, mc.promotion){
+                    println!("Could not make the move: {:?}", e);
+                }
+            }
+        }
+
+        fn key_down_event(&mut self, _ctx: &mut Context, keycode: ggez::event::KeyCode, keymods: ggez::event::KeyMods, repeat: bool) {
+            self.app.key_down_event(_ctx, keycode, keymods, repeat);
+        }
+
+        fn resize_event(&mut self, _ctx: &mut Context, width: f32, height: f32) {
+            self.app.resize_event(_ctx, width, height);
+        }
+
+        fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+            self.app.text_input_event(_ctx, character);
+        }
+    }
+}
+
+
 fn proto_build(){
     std::env::set_var("OUT_DIR", "/Users/antonmagnusson/Desktop/_prog/ru/INDA22PlusPlus-antmag-hw3/src");
     std::env::set_var("PROTOC", "/Users/antonmagnusson/Downloads/protoc-21/bin/protoc");
@@ -700,6 +1731,39 @@ fn main() {
     .build()
     .expect("Aeeeie!, could not create the ggez context!");
 
+    //--engine 'path' plays against a UCI engine, --analyse 'path' lets it analyse
+    //Both take an optional --book 'file' with a Polyglot opening book
+    let mode = std::env::args().nth(1);
+    //--puzzles 'file' starts the tactics trainer
+    if(mode.as_deref() == Some("--puzzles")){
+        let path = std::env::args().nth(2).expect("Expected a path to a puzzle file");
+        let puzzle_app = Puzzle_app::Puzzle_app::new(&mut ctx, &path);
+        event::run(ctx, event_loop, puzzle_app);
+    }
+    //--edit sets up a position and plays it on one board, --server --edit hosts it
+    if(mode.as_deref() == Some("--edit")){
+        let editor_app = Editor_app::Editor_app::new(&mut ctx);
+        event::run(ctx, event_loop, editor_app);
+    }
+
+    let engine_mode = match mode.as_deref() {
+        Some("--engine") => Some(Engine_app::ENGINE_MODE::PLAY),
+        Some("--analyse") => Some(Engine_app::ENGINE_MODE::ANALYSE),
+        _ => None
+    };
+    if let Some(engine_mode) = engine_mode{
+        let path = std::env::args().nth(2).expect("Expected a path to a UCI engine");
+        let book = match std::env::args().nth(3).as_deref() {
+            Some("--book") => {
+                let book_path = std::env::args().nth(4).expect("Expected a path to a book");
+                Some(INDA22PlusPlus_antmag_hw3::polyglot::Book::open(&book_path).expect("Could not read the book!"))
+            },
+            _ => None
+        };
+        let engine_app = Engine_app::Engine_app::new(&mut ctx, &path, engine_mode, book);
+        event::run(ctx, event_loop, engine_app);
+    }
+
     let mut net_app = Net_app::Net_app::new(&mut ctx);
     if(net_app.get_net_type() == NET_TYPE::CLIENT){
         net_app.ConnectRequest();
@@ -1,9 +1,15 @@
 
 use std::{
-    io::{Read, Write},
+    io::{self, Read, Write},
     net::{TcpListener, TcpStream}
 };
 
+use prost::Message;
+
+use crate::GameMove;
+use crate::piece::Definitions::PieceType;
+use crate::net_packet::{self, *};
+
 
 struct Net_state{
     fen : String,
@@ -16,8 +22,182 @@ pub fn from_fen(){
     
 }
 
+// the network Piece enum is sent as a raw i32
+pub fn piece_type_from_net(piece : i32) -> Option<PieceType>{
+    match net_packet::Piece::from_i32(piece)? {
+        net_packet::Piece::Pawn => Some(PieceType::Pawn),
+        net_packet::Piece::Knight => Some(PieceType::Knight),
+        net_packet::Piece::Bishop => Some(PieceType::Bishop),
+        net_packet::Piece::Rook => Some(PieceType::Rook),
+        net_packet::Piece::Queen => Some(PieceType::Queen),
+        net_packet::Piece::King => Some(PieceType::King),
+    }
+}
+
+pub fn piece_type_to_net(piece_type : PieceType) -> i32{
+    let piece = match piece_type {
+        PieceType::Pawn => net_packet::Piece::Pawn,
+        PieceType::Knight => net_packet::Piece::Knight,
+        PieceType::Bishop => net_packet::Piece::Bishop,
+        PieceType::Rook => net_packet::Piece::Rook,
+        PieceType::Queen => net_packet::Piece::Queen,
+        PieceType::King => net_packet::Piece::King,
+    };
+    piece as i32
+}
+
+pub fn move_to_net(mv : &GameMove) -> net_packet::Move{
+    net_packet::Move {
+        from_square : (mv.from.0 + mv.from.1 * 8) as u32,
+        to_square : (mv.to.0 + mv.to.1 * 8) as u32,
+        promotion : mv.promotion.map(piece_type_to_net)
+    }
+}
+
+// squares are sent as x + 8y in the board's own coordinates
+pub fn move_from_net(mv : &net_packet::Move) -> GameMove{
+    GameMove {
+        from : ((mv.from_square % 8) as usize, (mv.from_square / 8) as usize),
+        to : ((mv.to_square % 8) as usize, (mv.to_square / 8) as usize),
+        promotion : mv.promotion.and_then(piece_type_from_net)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NetRole{
+    Server,
+    Client
+}
+
+// every packet of the protocol, from either side
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetMessage{
+    ConnectRequest,
+    ConnectAck { success : bool, client_is_white : bool, fen : Option<String> },
+    Move(GameMove),
+    MoveAck(bool)
+}
+
+/* one end of a game over TCP for frontends without ggez. It speaks the same
+packets as the GUI, one packet per read, so the two can play each other */
+pub struct Connection{
+    stream : TcpStream,
+    role : NetRole
+}
+
+impl Connection{
+    // waits for one client
+    pub fn listen(addr : &str) -> io::Result<Connection>{
+        let listener = TcpListener::bind(addr)?;
+        let (stream, _) = listener.accept()?;
+        Ok(Connection::from_stream(stream, NetRole::Server))
+    }
+
+    pub fn connect(addr : &str) -> io::Result<Connection>{
+        Ok(Connection::from_stream(TcpStream::connect(addr)?, NetRole::Client))
+    }
+
+    pub fn from_stream(stream : TcpStream, role : NetRole) -> Connection{
+        Connection { stream : stream, role : role }
+    }
+
+    pub fn role(&self) -> NetRole{
+        self.role
+    }
+
+    // a second handle to the same socket, e.g. for a thread that only reads
+    pub fn try_clone(&self) -> io::Result<Connection>{
+        Ok(Connection::from_stream(self.stream.try_clone()?, self.role))
+    }
+
+    pub fn send(&mut self, msg : &NetMessage) -> io::Result<()>{
+        let buffer = match (self.role, msg) {
+            (NetRole::Client, NetMessage::ConnectRequest) => c2s(c2s_message::Msg::ConnectRequest(C2sConnectRequest { game_id : 0, spectate : false })),
+            (NetRole::Client, NetMessage::Move(mv)) => c2s(c2s_message::Msg::Move(move_to_net(mv))),
+            (NetRole::Server, NetMessage::Move(mv)) => s2c(s2c_message::Msg::Move(move_to_net(mv))),
+            (NetRole::Server, NetMessage::MoveAck(legal)) => s2c(s2c_message::Msg::MoveAck(S2cMoveAck { legal : *legal, board_result : None })),
+            (NetRole::Server, NetMessage::ConnectAck { success, client_is_white, fen }) => s2c(s2c_message::Msg::ConnectAck(S2cConnectAck {
+                success : *success,
+                game_id : Some(0),
+                starting_position : fen.clone().map(|fen| BoardState { fen_string : fen }),
+                client_is_white : Some(*client_is_white)
+            })),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{:?} can not be sent by the {:?}", msg, self.role)))
+        };
+        self.stream.write_all(&buffer)
+    }
+
+    // blocks until the other side sends something, None for a packet without a message
+    pub fn receive(&mut self) -> io::Result<Option<NetMessage>>{
+        let mut buffer = [0_u8; 512];
+        let n = self.stream.read(&mut buffer)?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the other side closed the connection"));
+        }
+        let invalid = |e : prost::DecodeError| io::Error::new(io::ErrorKind::InvalidData, e);
+        let msg = match self.role {
+            NetRole::Server => match C2sMessage::decode(&buffer[..n]).map_err(invalid)?.msg {
+                Some(c2s_message::Msg::ConnectRequest(_)) => NetMessage::ConnectRequest,
+                Some(c2s_message::Msg::Move(mv)) => NetMessage::Move(move_from_net(&mv)),
+                None => return Ok(None)
+            },
+            NetRole::Client => match S2cMessage::decode(&buffer[..n]).map_err(invalid)?.msg {
+                Some(s2c_message::Msg::ConnectAck(ack)) => NetMessage::ConnectAck {
+                    success : ack.success,
+                    client_is_white : ack.client_is_white.unwrap_or(true),
+                    fen : ack.starting_position.map(|position| position.fen_string)
+                },
+                Some(s2c_message::Msg::Move(mv)) => NetMessage::Move(move_from_net(&mv)),
+                Some(s2c_message::Msg::MoveAck(ack)) => NetMessage::MoveAck(ack.legal),
+                None => return Ok(None)
+            }
+        };
+        Ok(Some(msg))
+    }
+}
+
+fn c2s(msg : c2s_message::Msg) -> Vec<u8>{
+    C2sMessage { msg : Some(msg) }.encode_to_vec()
+}
+
+fn s2c(msg : s2c_message::Msg) -> Vec<u8>{
+    S2cMessage { msg : Some(msg) }.encode_to_vec()
+}
+
 
 fn main(){
     
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_round_trip() {
+        let mv = GameMove { from : (4, 1), to : (4, 0), promotion : Some(PieceType::Knight) };
+        assert_eq!(move_from_net(&move_to_net(&mv)), mv);
+        assert_eq!(move_to_net(&mv).to_square, 4);
+    }
+
+    #[test]
+    fn server_and_client_talk() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = Connection::connect(&listener.local_addr().unwrap().to_string()).unwrap();
+        let mut server = Connection::from_stream(listener.accept().unwrap().0, NetRole::Server);
+
+        client.send(&NetMessage::ConnectRequest).unwrap();
+        assert_eq!(server.receive().unwrap(), Some(NetMessage::ConnectRequest));
+        let ack = NetMessage::ConnectAck { success : true, client_is_white : true, fen : Some(String::from(crate::fen::START_FEN)) };
+        server.send(&ack).unwrap();
+        assert_eq!(client.receive().unwrap(), Some(ack));
+
+        let mv = GameMove { from : (4, 6), to : (4, 4), promotion : None };
+        client.send(&NetMessage::Move(mv)).unwrap();
+        assert_eq!(server.receive().unwrap(), Some(NetMessage::Move(mv)));
+        server.send(&NetMessage::MoveAck(true)).unwrap();
+        assert_eq!(client.receive().unwrap(), Some(NetMessage::MoveAck(true)));
+        // only the server acknowledges moves
+        assert!(client.send(&NetMessage::MoveAck(true)).is_err());
+    }
+}
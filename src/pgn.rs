@@ -0,0 +1,336 @@
+use crate::*;
+use crate::fen::{parse_square, piece_type_from_char, square_name};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PgnError {
+    InvalidFen(String),
+    // a move that is not legal in its game, with the game's index in the file
+    IllegalMove { game: usize, san: String },
+    // a move of a PgnGame that can not be played, counted in plies from its start,
+    // with the game's index in the file when the caller knows it
+    UnplayableMove { game: Option<usize>, ply: usize, uci: String },
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PgnGame {
+    // in the order they appear, e.g. ("White", "Carlsen")
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<GameMove>,
+    // "1-0", "0-1", "1/2-1/2" or "*"
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    // the FEN tag if there is one, otherwise the normal start
+    pub fn start_position(&self) -> Result<Game, PgnError> {
+        match self.tag("FEN") {
+            Some(fen) => Game::from_fen(fen).map_err(|_| PgnError::InvalidFen(fen.to_string())),
+            None => Ok(create_game()),
+        }
+    }
+
+    // the game as PGN text, move numbers follow the start position
+    pub fn to_pgn(&self) -> Result<String, PgnError> {
+        self.to_pgn_with(|_| vec![])
+    }
+
+    /* like to_pgn with extra tokens after each move, e.g. "$2" or "{a comment}".
+    The annotations get the index of the move */
+    pub fn to_pgn_with<F: FnMut(usize) -> Vec<String>>(&self, mut annotations: F) -> Result<String, PgnError> {
+        let mut text = String::new();
+        for (name, value) in self.tags.iter() {
+            text += &format!("[{} \"{}\"]\n", name, value.replace('"', "'"));
+        }
+        text.push('\n');
+
+        let mut game = self.start_position()?;
+        let mut tokens = vec![];
+        // black's move needs its number again after a comment
+        let mut interrupted = true;
+        for (i, mv) in self.moves.iter().enumerate() {
+            if game.turn == Color::White {
+                tokens.push(format!("{}.", game.fullmove_number));
+            } else if interrupted {
+                tokens.push(format!("{}...", game.fullmove_number));
+            }
+            tokens.push(game.to_san(mv));
+            game.make_move(mv).map_err(|_| PgnError::UnplayableMove { game: None, ply: i, uci: mv.to_uci() })?;
+            let extra = annotations(i);
+            interrupted = extra.iter().any(|token| token.starts_with('{'));
+            tokens.extend(extra);
+        }
+        tokens.push(if self.result.is_empty() { String::from("*") } else { self.result.clone() });
+
+        // lines of at most 80 characters like most tools write
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > 80 {
+                text += &line;
+                text.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line += &token;
+        }
+        text += &line;
+        text.push('\n');
+        Ok(text)
+    }
+}
+
+fn piece_letter(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::Pawn => "",
+        PieceType::Knight => "N",
+        PieceType::Bishop => "B",
+        PieceType::Rook => "R",
+        PieceType::Queen => "Q",
+        PieceType::King => "K",
+    }
+}
+
+impl Game {
+    fn piece_type_at(&self, xy: (usize, usize)) -> Option<PieceType> {
+        match self.board[xy.1][xy.0] {
+            Content::Occupied(p) => Some(p.piece_type),
+            Content::Empty => None,
+        }
+    }
+
+    // standard algebraic notation for a legal move, e.g. Nbd7, exd5, e8=Q+ or O-O
+    pub fn to_san(&self, mv: &GameMove) -> String {
+        let piece_type = self.piece_type_at(mv.from).unwrap_or(PieceType::Pawn);
+        let mut san = String::new();
+
+        if piece_type == PieceType::King && (mv.to.0 as i32 - mv.from.0 as i32).abs() == 2 {
+            san += if mv.to.0 > mv.from.0 { "O-O" } else { "O-O-O" };
+        } else {
+            let capture = self.piece_type_at(mv.to).is_some();
+            san += piece_letter(piece_type);
+            if piece_type == PieceType::Pawn {
+                if capture {
+                    san.push((b'a' + mv.from.0 as u8) as char);
+                }
+            } else {
+                // DISAMBIGUATION: file if it is enough, then rank, then both
+                let others: Vec<GameMove> = self.legal_moves().into_iter()
+                    .filter(|other| other.to == mv.to && other.from != mv.from && self.piece_type_at(other.from) == Some(piece_type))
+                    .collect();
+                if !others.is_empty() {
+                    let from = square_name(mv.from);
+                    if others.iter().all(|other| other.from.0 != mv.from.0) {
+                        san.push_str(&from[0..1]);
+                    } else if others.iter().all(|other| other.from.1 != mv.from.1) {
+                        san.push_str(&from[1..2]);
+                    } else {
+                        san += &from;
+                    }
+                }
+            }
+            if capture {
+                san.push('x');
+            }
+            san += &square_name(mv.to);
+            if let Some(new_piece_type) = mv.promotion {
+                san.push('=');
+                san += piece_letter(new_piece_type);
+            }
+        }
+
+        let mut after = self.detached();
+        if after.make_move(mv).is_ok() && after.is_checked(after.turn) {
+            san.push(if after.legal_moves().is_empty() { '#' } else { '+' });
+        }
+        san
+    }
+
+    // a legal move from standard algebraic notation, check marks and annotations are ignored
+    pub fn parse_san(&self, text: &str) -> Option<GameMove> {
+        let san = text.trim_end_matches(['+', '#', '!', '?']);
+        let legal = self.legal_moves();
+
+        if san == "O-O" || san == "0-0" || san == "O-O-O" || san == "0-0-0" {
+            let king = match self.turn { Color::White => self.w_king, Color::Black => self.b_king };
+            let to_x = if san.len() == 3 { king.0 + 2 } else { king.0.checked_sub(2)? };
+            return legal.into_iter().find(|mv| mv.from == king && mv.to == (to_x, king.1));
+        }
+
+        // PROMOTION, "e8=Q" and the older "e8Q"
+        let (san, promotion) = match san.char_indices().last() {
+            Some((i, c)) if i >= 2 && "QRBN".contains(c) => {
+                (san[..i].trim_end_matches('='), Some(piece_type_from_char(c)?))
+            },
+            _ => (san, None),
+        };
+        if san.len() < 2 || !san.is_ascii() {
+            return None;
+        }
+        let to = parse_square(&san[san.len() - 2..])?;
+        let mut rest = &san[..san.len() - 2];
+        let piece_type = match rest.chars().next() {
+            Some(c) if c.is_ascii_uppercase() => {
+                rest = &rest[1..];
+                piece_type_from_char(c)?
+            },
+            _ => PieceType::Pawn,
+        };
+        // what is left is the disambiguation, maybe followed by x
+        let hint: Vec<char> = rest.chars().filter(|&c| c != 'x').collect();
+
+        let mut matches = legal.into_iter().filter(|mv| {
+            mv.to == to
+                && mv.promotion == promotion
+                && self.piece_type_at(mv.from) == Some(piece_type)
+                && hint.iter().all(|&c| match c {
+                    'a'..='h' => mv.from.0 == (c as u8 - b'a') as usize,
+                    '1'..='8' => mv.from.1 == (b'8' - c as u8) as usize,
+                    _ => false,
+                })
+        });
+        let mv = matches.next()?;
+        // an ambiguous move is as bad as an illegal one
+        if matches.next().is_some() { None } else { Some(mv) }
+    }
+}
+
+fn is_result(token: &str) -> bool {
+    matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*")
+}
+
+/* every game in a PGN file, moves are checked by playing them out.
+Comments, variations and NAGs are skipped */
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut games = vec![];
+    let mut current = PgnGame::default();
+    let mut position: Option<Game> = None;
+    let mut has_moves = false;
+    let mut variation_depth = 0;
+    // comments can run over several lines
+    let mut in_comment = false;
+
+    for line in text.lines() {
+        let line = line.trim();
+        // TAGS
+        if line.starts_with('[') && variation_depth == 0 && !in_comment {
+            if has_moves {
+                // a game without a result before the next tags
+                current.result = String::from("*");
+                games.push(std::mem::take(&mut current));
+                position = None;
+                has_moves = false;
+            }
+            let inner = line.trim_start_matches('[').trim_end_matches(']');
+            if let Some((name, value)) = inner.split_once(' ') {
+                current.tags.push((name.to_string(), value.trim().trim_matches('"').to_string()));
+            }
+            continue;
+        }
+
+        // MOVES, split so braces and parentheses are tokens of their own
+        let spaced = line.replace('{', " { ").replace('}', " } ").replace('(', " ( ").replace(')', " ) ");
+        for token in spaced.split_whitespace() {
+            if in_comment {
+                in_comment = token != "}";
+                continue;
+            }
+            match token {
+                "{" => in_comment = true,
+                "(" => variation_depth += 1,
+                ")" => variation_depth -= 1,
+                _ if token.starts_with(';') => break,
+                _ if variation_depth > 0 || token.starts_with('$') => (),
+                _ if is_result(token) => {
+                    current.result = token.to_string();
+                    games.push(std::mem::take(&mut current));
+                    position = None;
+                    has_moves = false;
+                },
+                _ => {
+                    // "12." "12..." and "1.e4", only a number ending in a dot so 0-0 stays castling
+                    let number = token.trim_start_matches(|c: char| c.is_ascii_digit());
+                    let san = if number.starts_with('.') { number.trim_start_matches('.') } else { token };
+                    if san.is_empty() {
+                        continue;
+                    }
+                    if position.is_none() {
+                        position = Some(current.start_position()?);
+                    }
+                    let game = position.as_mut().expect("position was just set");
+                    let mv = game.parse_san(san).ok_or(PgnError::IllegalMove {
+                        game: games.len(),
+                        san: san.to_string(),
+                    })?;
+                    game.make_move(&mv).expect("parse_san only returns legal moves");
+                    current.moves.push(mv);
+                    has_moves = true;
+                }
+            }
+        }
+    }
+    if has_moves {
+        current.result = String::from("*");
+        games.push(current);
+    }
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn san_round_trip() {
+        let mut game = Game::from_fen("r3k2r/1P6/8/8/8/2N3N1/8/R3K2R w KQkq - 0 1").unwrap();
+        for san in ["O-O", "O-O-O", "Nce4", "Nge4", "b8=Q+", "bxa8=N", "Rxa8+"] {
+            let mv = game.parse_san(san).unwrap();
+            assert_eq!(game.to_san(&mv), san);
+        }
+        assert_eq!(game.parse_san("Ne4"), None);
+        // the rook on f1 and the pawn on b7 stop black castling
+        game.make_move(&game.parse_san("Rf1").unwrap()).unwrap();
+        assert_eq!(game.parse_san("O-O"), None);
+        assert_eq!(game.parse_san("O-O-O"), None);
+        assert_eq!(game.to_san(&game.parse_san("Kd7").unwrap()), "Kd7");
+    }
+
+    #[test]
+    fn reads_games() {
+        let text = "[Event \"Test\"]\n[White \"A\"]\n\n1. e4 {best by test} e5 2. Nf3 (2. f4 exf4) Nc6 $1 1-0\n\n\
+            [Event \"Mate\"]\n\n1.f3 e5 2.g4 Qh4# 0-1\n";
+        let games = parse_pgn(text).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("White"), Some("A"));
+        assert_eq!(games[0].moves.len(), 4);
+        assert_eq!(games[0].result, "1-0");
+        assert_eq!(games[1].moves[3].to_uci(), "d8h4");
+        assert_eq!(parse_pgn("1. e4 e4").err(), Some(PgnError::IllegalMove { game: 0, san: String::from("e4") }));
+    }
+
+    #[test]
+    fn castling_with_zeros() {
+        let text = "1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4.0-0 d6 5. d3 Bg4 6. Nc3 Qd7 7. Be3 0-0-0 *";
+        let game = parse_pgn(text).unwrap().remove(0);
+        assert_eq!(game.moves.len(), 14);
+        assert_eq!(game.moves[6].to_uci(), "e1g1");
+        assert_eq!(game.moves[13].to_uci(), "e8c8");
+    }
+
+    #[test]
+    fn writes_games() {
+        let game = parse_pgn("[FEN \"6k1/5ppp/8/8/8/8/8/R5K1 b - - 0 7\"]\n\n7... h6 8. Ra8+ Kh7 *").unwrap().remove(0);
+        let text = game.to_pgn().unwrap();
+        assert_eq!(text, "[FEN \"6k1/5ppp/8/8/8/8/8/R5K1 b - - 0 7\"]\n\n7... h6 8. Ra8+ Kh7 *\n");
+        assert_eq!(parse_pgn(&text).unwrap()[0].moves, game.moves);
+
+        // a move that can not be played is reported by its ply, the game does not know its index
+        let mut broken = game.clone();
+        broken.moves.insert(1, GameMove { from: (0, 0), to: (0, 4), promotion: None });
+        assert_eq!(broken.to_pgn().err(), Some(PgnError::UnplayableMove { game: None, ply: 1, uci: String::from("a8a4") }));
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::*;
+use crate::pgn::{parse_pgn, PgnError, PgnGame};
+use crate::polyglot_keys::RANDOM64;
+
+// key, move, weight and learn, all big endian
+const ENTRY_SIZE: usize = 16;
+const CASTLE_OFFSET: usize = 768;
+const TURN_OFFSET: usize = 780;
+
+#[derive(Debug)]
+pub enum BookError {
+    Io(io::Error),
+    // the file is not a whole number of entries
+    BadLength(usize),
+    Pgn(PgnError),
+}
+
+impl From<io::Error> for BookError {
+    fn from(e: io::Error) -> BookError {
+        BookError::Io(e)
+    }
+}
+
+impl From<PgnError> for BookError {
+    fn from(e: PgnError) -> BookError {
+        BookError::Pgn(e)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BookEntry {
+    pub key: u64,
+    // the move as Polyglot packs it, see encode_move
+    pub raw_move: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+/* the standard Polyglot key, so books made by other tools work.
+The game has no en passant, so those keys are never used */
+pub fn polyglot_key(game: &Game) -> u64 {
+    let mut key = 0;
+    for y in 0..BOARD_SIZE {
+        for x in 0..BOARD_SIZE {
+            if let Content::Occupied(p) = game.board[y][x] {
+                let type_index = match p.piece_type {
+                    PieceType::Pawn => 0,
+                    PieceType::Knight => 1,
+                    PieceType::Bishop => 2,
+                    PieceType::Rook => 3,
+                    PieceType::Queen => 4,
+                    PieceType::King => 5,
+                };
+                // black pieces come first, squares count from a1
+                let kind = 2 * type_index + if p.color == Color::White { 1 } else { 0 };
+                key ^= RANDOM64[64 * kind + 8 * (7 - y) + x];
+            }
+        }
+    }
+    // the game's mask is queenside then kingside, Polyglot wants K Q k q
+    let rights = game.castle_rights_mask();
+    for (i, bit) in [2, 1, 8, 4].iter().enumerate() {
+        if rights & bit != 0 {
+            key ^= RANDOM64[CASTLE_OFFSET + i];
+        }
+    }
+    if game.turn == Color::White {
+        key ^= RANDOM64[TURN_OFFSET];
+    }
+    key
+}
+
+/* to file bits 0-2, to rank 3-5, from file 6-8, from rank 9-11, promotion 12-14.
+Castling is written as the king taking its own rook */
+pub fn encode_move(game: &Game, mv: &GameMove) -> u16 {
+    let mut to = mv.to;
+    if let Content::Occupied(p) = game.board[mv.from.1][mv.from.0] {
+        if p.piece_type == PieceType::King && (mv.to.0 as i32 - mv.from.0 as i32).abs() == 2 {
+            to.0 = if mv.to.0 > mv.from.0 { 7 } else { 0 };
+        }
+    }
+    let promotion = match mv.promotion {
+        None => 0,
+        Some(PieceType::Knight) => 1,
+        Some(PieceType::Bishop) => 2,
+        Some(PieceType::Rook) => 3,
+        Some(_) => 4,
+    };
+    (to.0 | (7 - to.1) << 3 | mv.from.0 << 6 | (7 - mv.from.1) << 9 | promotion << 12) as u16
+}
+
+// the legal move a book move stands for in this game
+pub fn decode_move(game: &Game, raw: u16) -> Option<GameMove> {
+    let raw = raw as usize;
+    let from = ((raw >> 6) & 7, 7 - ((raw >> 9) & 7));
+    let mut to = (raw & 7, 7 - ((raw >> 3) & 7));
+    let promotion = match (raw >> 12) & 7 {
+        0 => None,
+        1 => Some(PieceType::Knight),
+        2 => Some(PieceType::Bishop),
+        3 => Some(PieceType::Rook),
+        4 => Some(PieceType::Queen),
+        _ => return None,
+    };
+    if let Content::Occupied(p) = game.board[from.1][from.0] {
+        if p.piece_type == PieceType::King && from.0 == 4 && from.1 == to.1 && (to.0 == 0 || to.0 == 7) {
+            to.0 = if to.0 == 7 { 6 } else { 2 };
+        }
+    }
+    let mv = GameMove { from: from, to: to, promotion: promotion };
+    if game.legal_moves().contains(&mv) { Some(mv) } else { None }
+}
+
+// xorshift64*, seeded so tests and replays can pick the same moves
+#[derive(Clone, Debug)]
+pub struct BookRng {
+    state: u64,
+}
+
+impl BookRng {
+    pub fn new(seed: u64) -> BookRng {
+        // the state must never be 0
+        BookRng { state: seed ^ 0x9e37_79b9_7f4a_7c15 | 1 }
+    }
+
+    pub fn from_time() -> BookRng {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+        BookRng::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // a number in 0..n, n must not be 0
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Book {
+    // sorted by key, the best weight first within a key
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    pub fn new(mut entries: Vec<BookEntry>) -> Book {
+        entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)));
+        Book { entries: entries }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Book, BookError> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(BookError::BadLength(bytes.len()));
+        }
+        let entries = bytes.chunks(ENTRY_SIZE).map(|chunk| BookEntry {
+            key: u64::from_be_bytes(chunk[0..8].try_into().expect("chunk is 16 bytes")),
+            raw_move: u16::from_be_bytes([chunk[8], chunk[9]]),
+            weight: u16::from_be_bytes([chunk[10], chunk[11]]),
+            learn: u32::from_be_bytes(chunk[12..16].try_into().expect("chunk is 16 bytes")),
+        }).collect();
+        Ok(Book::new(entries))
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Book, BookError> {
+        Book::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.entries.len() * ENTRY_SIZE);
+        for entry in self.entries.iter() {
+            bytes.extend_from_slice(&entry.key.to_be_bytes());
+            bytes.extend_from_slice(&entry.raw_move.to_be_bytes());
+            bytes.extend_from_slice(&entry.weight.to_be_bytes());
+            bytes.extend_from_slice(&entry.learn.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), BookError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self, key: u64) -> &[BookEntry] {
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let end = self.entries.partition_point(|entry| entry.key <= key);
+        &self.entries[start..end]
+    }
+
+    // legal book moves for the position with their weights, the best first
+    pub fn moves(&self, game: &Game) -> Vec<(GameMove, u16)> {
+        self.entries(polyglot_key(game)).iter()
+            .filter_map(|entry| decode_move(game, entry.raw_move).map(|mv| (mv, entry.weight)))
+            .collect()
+    }
+
+    pub fn best_move(&self, game: &Game) -> Option<GameMove> {
+        self.moves(game).first().map(|(mv, _)| *mv)
+    }
+
+    // a move picked with chances in proportion to the weights
+    pub fn pick(&self, game: &Game, rng: &mut BookRng) -> Option<GameMove> {
+        let moves = self.moves(game);
+        let total: u64 = moves.iter().map(|(_, weight)| *weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = rng.below(total);
+        for (mv, weight) in moves {
+            if roll < weight as u64 {
+                return Some(mv);
+            }
+            roll -= weight as u64;
+        }
+        None
+    }
+}
+
+/* counts the moves of a game collection like polyglot make-book:
+2 points for a move by the winner, 1 for a draw, 0 for the loser */
+pub struct BookBuilder {
+    max_ply: usize,
+    scores: HashMap<(u64, u16), u64>,
+}
+
+impl BookBuilder {
+    pub fn new(max_ply: usize) -> BookBuilder {
+        BookBuilder { max_ply: max_ply, scores: HashMap::new() }
+    }
+
+    pub fn add_game(&mut self, pgn_game: &PgnGame) -> Result<(), PgnError> {
+        let mut game = pgn_game.start_position()?;
+        for mv in pgn_game.moves.iter().take(self.max_ply) {
+            let score = match (pgn_game.result.as_str(), game.turn) {
+                ("1-0", Color::White) | ("0-1", Color::Black) => 2,
+                ("1-0", _) | ("0-1", _) => 0,
+                _ => 1,
+            };
+            *self.scores.entry((polyglot_key(&game), encode_move(&game, mv))).or_insert(0) += score;
+            game.make_move(mv).expect("parse_pgn only returns legal moves");
+        }
+        Ok(())
+    }
+
+    // the number of games added
+    pub fn add_pgn(&mut self, text: &str) -> Result<usize, BookError> {
+        let games = parse_pgn(text)?;
+        for pgn_game in games.iter() {
+            self.add_game(pgn_game)?;
+        }
+        Ok(games.len())
+    }
+
+    pub fn build(&self) -> Book {
+        // weights are 16 bits, so big collections are scaled down
+        let max = self.scores.values().copied().max().unwrap_or(0);
+        let scale = if max > u16::MAX as u64 { max as f64 / u16::MAX as f64 } else { 1.0 };
+        let entries = self.scores.iter()
+            .map(|(&(key, raw_move), &score)| BookEntry {
+                key: key,
+                raw_move: raw_move,
+                weight: (score as f64 / scale) as u16,
+                learn: 0,
+            })
+            .filter(|entry| entry.weight > 0)
+            .collect();
+        Book::new(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard_keys() {
+        // the examples from the Polyglot format description
+        let mut game = create_game();
+        assert_eq!(polyglot_key(&game), 0x463b96181691fc9c);
+        game.make_move(&game.parse_uci_move("e2e4").unwrap()).unwrap();
+        assert_eq!(polyglot_key(&game), 0x823c9b50fd114196);
+        game.make_move(&game.parse_uci_move("d7d5").unwrap()).unwrap();
+        game.make_move(&game.parse_uci_move("e4e5").unwrap()).unwrap();
+        game.make_move(&game.parse_uci_move("f7f5").unwrap()).unwrap();
+        // the real key has en passant on f6, which the game does not have
+        assert_eq!(polyglot_key(&game) ^ RANDOM64[772 + 5], 0x22a48b5a8e47ff78);
+        game.make_move(&game.parse_uci_move("e1e2").unwrap()).unwrap();
+        assert_eq!(polyglot_key(&game), 0x652a607ca3f242c1);
+    }
+
+    #[test]
+    fn castling_moves() {
+        let game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let castle = GameMove { from: (4, 7), to: (6, 7), promotion: None };
+        // e1h1
+        assert_eq!(encode_move(&game, &castle), 7 | 4 << 6);
+        assert_eq!(decode_move(&game, 7 | 4 << 6), Some(castle));
+    }
+
+    #[test]
+    fn build_and_read_book() {
+        let mut builder = BookBuilder::new(4);
+        let pgn = "1. e4 e5 2. Nf3 Nc6 1-0\n1. e4 c5 2. Nf3 d6 0-1\n1. d4 d5 1/2-1/2\n";
+        assert_eq!(builder.add_pgn(pgn).unwrap(), 3);
+        let book = Book::from_bytes(&builder.build().to_bytes()).unwrap();
+
+        let game = create_game();
+        let moves = book.moves(&game);
+        assert_eq!(moves.len(), 2);
+        assert_eq!(book.best_move(&game), game.parse_uci_move("e2e4"));
+        assert_eq!(moves[0].1, 2);
+
+        let mut rng = BookRng::new(7);
+        let picks: Vec<_> = (0..50).map(|_| book.pick(&game, &mut rng).unwrap()).collect();
+        assert!(picks.contains(&game.parse_uci_move("d2d4").unwrap()));
+        let mut again = BookRng::new(7);
+        assert_eq!(book.pick(&game, &mut again), Some(picks[0]));
+        assert!(matches!(Book::from_bytes(&[0; 15]), Err(BookError::BadLength(15))));
+    }
+}
@@ -0,0 +1,261 @@
+use crate::*;
+use crate::threats::other;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PositionError {
+    MissingKing(Color),
+    TooManyKings(Color),
+    PawnOnBackRank((usize, usize)),
+    // the side that just moved can not have left its king in check
+    OpponentInCheck(Color),
+    // the king or the rook of the right is not on its starting square
+    ImpossibleCastling { color: Color, kingside: bool },
+    ImpossibleEnPassant((usize, usize)),
+}
+
+/* sets up any position square by square, build() checks that it could have
+come from a real game. Castling rights use the same bits as the game:
+white queenside, white kingside, black queenside, black kingside */
+#[derive(Clone, Debug)]
+pub struct PositionBuilder {
+    board: Board,
+    turn: Color,
+    castling: u8,
+    en_passant: Option<(usize, usize)>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+}
+
+impl Default for PositionBuilder {
+    fn default() -> PositionBuilder {
+        PositionBuilder::new()
+    }
+}
+
+fn home_rank(color: Color) -> usize {
+    match color { Color::White => 7, Color::Black => 0 }
+}
+
+fn castling_bit(color: Color, kingside: bool) -> u8 {
+    let bit = if kingside { 2 } else { 1 };
+    match color { Color::White => bit, Color::Black => bit << 2 }
+}
+
+impl PositionBuilder {
+    // an empty board with white to move
+    pub fn new() -> PositionBuilder {
+        PositionBuilder {
+            board: [[Content::Empty; BOARD_SIZE]; BOARD_SIZE],
+            turn: Color::White,
+            castling: 0,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        }
+    }
+
+    // starts from an existing game, for editing it
+    pub fn from_game(game: &Game) -> PositionBuilder {
+        PositionBuilder {
+            board: game.board,
+            turn: game.turn,
+            castling: game.castle_rights_mask(),
+            en_passant: None,
+            halfmove_clock: game.halfmove_clock,
+            fullmove_number: game.fullmove_number,
+        }
+    }
+
+    pub fn place(&mut self, xy: (usize, usize), color: Color, piece_type: PieceType) -> &mut PositionBuilder {
+        self.board[xy.1][xy.0] = Content::Occupied(Piece { color: color, piece_type: piece_type, times_moved: 1 });
+        self
+    }
+
+    pub fn remove(&mut self, xy: (usize, usize)) -> &mut PositionBuilder {
+        self.board[xy.1][xy.0] = Content::Empty;
+        self
+    }
+
+    pub fn clear(&mut self) -> &mut PositionBuilder {
+        self.board = [[Content::Empty; BOARD_SIZE]; BOARD_SIZE];
+        self.castling = 0;
+        self.en_passant = None;
+        self
+    }
+
+    pub fn side_to_move(&mut self, color: Color) -> &mut PositionBuilder {
+        self.turn = color;
+        self
+    }
+
+    pub fn castling(&mut self, color: Color, kingside: bool, allowed: bool) -> &mut PositionBuilder {
+        if allowed {
+            self.castling |= castling_bit(color, kingside);
+        } else {
+            self.castling &= !castling_bit(color, kingside);
+        }
+        self
+    }
+
+    // the square behind a pawn that just moved two squares, like in FEN
+    pub fn en_passant(&mut self, square: Option<(usize, usize)>) -> &mut PositionBuilder {
+        self.en_passant = square;
+        self
+    }
+
+    pub fn move_counters(&mut self, halfmove_clock: u32, fullmove_number: u32) -> &mut PositionBuilder {
+        self.halfmove_clock = halfmove_clock;
+        self.fullmove_number = fullmove_number.max(1);
+        self
+    }
+
+    pub fn piece_at(&self, xy: (usize, usize)) -> Option<Piece> {
+        threats::piece_on(&self.board, xy)
+    }
+
+    pub fn turn(&self) -> Color {
+        self.turn
+    }
+
+    pub fn has_castling(&self, color: Color, kingside: bool) -> bool {
+        self.castling & castling_bit(color, kingside) != 0
+    }
+
+    fn is(&self, xy: (usize, usize), color: Color, piece_type: PieceType) -> bool {
+        self.piece_at(xy).is_some_and(|p| p.color == color && p.piece_type == piece_type)
+    }
+
+    fn validate(&self) -> Result<(), PositionError> {
+        for color in [Color::White, Color::Black] {
+            let kings = self.board.iter().flatten()
+                .filter(|c| matches!(c, Content::Occupied(p) if p.color == color && p.piece_type == PieceType::King))
+                .count();
+            match kings {
+                0 => return Err(PositionError::MissingKing(color)),
+                1 => (),
+                _ => return Err(PositionError::TooManyKings(color)),
+            }
+        }
+        for y in [0, BOARD_SIZE - 1] {
+            for x in 0..BOARD_SIZE {
+                if matches!(self.board[y][x], Content::Occupied(p) if p.piece_type == PieceType::Pawn) {
+                    return Err(PositionError::PawnOnBackRank((x, y)));
+                }
+            }
+        }
+        for color in [Color::White, Color::Black] {
+            let y = home_rank(color);
+            for (kingside, rook_x) in [(false, 0), (true, 7)] {
+                if self.has_castling(color, kingside) && !(self.is((4, y), color, PieceType::King) && self.is((rook_x, y), color, PieceType::Rook)) {
+                    return Err(PositionError::ImpossibleCastling { color: color, kingside: kingside });
+                }
+            }
+        }
+        if let Some(square) = self.en_passant {
+            // the pawn that moved stands in front of the square, the two squares it crossed are empty
+            let (rank, dy) = match self.turn { Color::White => (2, 1), Color::Black => (5, -1) };
+            let pawn = (square.0, (square.1 as i32 + dy) as usize);
+            let start = (square.0, (square.1 as i32 - dy) as usize);
+            if square.1 != rank || !self.is(pawn, other(self.turn), PieceType::Pawn)
+                || self.piece_at(square).is_some() || self.piece_at(start).is_some() {
+                return Err(PositionError::ImpossibleEnPassant(square));
+            }
+        }
+        Ok(())
+    }
+
+    /* the game has no en passant captures, so a valid en passant square is
+    checked and then dropped like in Game::from_fen */
+    pub fn build(&self) -> Result<Game, PositionError> {
+        self.validate()?;
+        let mut game = create_game();
+        game.board = self.board;
+        for y in 0..BOARD_SIZE {
+            for x in 0..BOARD_SIZE {
+                if let Content::Occupied(mut p) = game.board[y][x] {
+                    // only pawns on their first rank may still move two squares
+                    let start_rank = match p.color { Color::White => 6, Color::Black => 1 };
+                    let unmoved = p.piece_type == PieceType::Pawn && y == start_rank;
+                    p.times_moved = if unmoved { 0 } else { 1 };
+                    game.board[y][x] = Content::Occupied(p);
+                }
+            }
+        }
+        // the king and rook of every right are marked as unmoved
+        for color in [Color::White, Color::Black] {
+            let y = home_rank(color);
+            for (kingside, rook_x) in [(false, 0), (true, 7)] {
+                if self.has_castling(color, kingside) {
+                    for x in [4, rook_x] {
+                        if let Content::Occupied(mut p) = game.board[y][x] {
+                            p.times_moved = 0;
+                            game.board[y][x] = Content::Occupied(p);
+                        }
+                    }
+                }
+            }
+        }
+        game.turn = self.turn;
+        game.halfmove_clock = self.halfmove_clock;
+        game.fullmove_number = self.fullmove_number;
+        game.sync_state();
+
+        if game.is_checked(other(self.turn)) {
+            return Err(PositionError::OpponentInCheck(other(self.turn)));
+        }
+        Ok(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::parse_square;
+
+    fn sq(name: &str) -> (usize, usize) {
+        parse_square(name).unwrap()
+    }
+
+    #[test]
+    fn builds_positions() {
+        let game = PositionBuilder::new()
+            .place(sq("e1"), Color::White, PieceType::King)
+            .place(sq("h1"), Color::White, PieceType::Rook)
+            .place(sq("e8"), Color::Black, PieceType::King)
+            .place(sq("d7"), Color::Black, PieceType::Pawn)
+            .castling(Color::White, true, true)
+            .side_to_move(Color::Black)
+            .move_counters(3, 20)
+            .build()
+            .unwrap();
+        assert_eq!(game.to_fen(), "4k3/3p4/8/8/8/8/8/4K2R b K - 3 20");
+        assert_eq!(game.hash(), Game::from_fen("4k3/3p4/8/8/8/8/8/4K2R b K - 3 20").unwrap().hash());
+        // the black pawn can still move two squares
+        assert!(game.legal_moves().contains(&GameMove { from: sq("d7"), to: sq("d5"), promotion: None }));
+
+        let mut builder = PositionBuilder::from_game(&create_game());
+        assert!(builder.has_castling(Color::Black, false));
+        builder.remove(sq("g1")).remove(sq("f1")).remove(sq("e2"));
+        assert_eq!(builder.build().unwrap().to_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPP1PPP/RNBQK2R w KQkq - 0 1");
+    }
+
+    #[test]
+    fn rejects_impossible_positions() {
+        let mut builder = PositionBuilder::new();
+        assert_eq!(builder.build().err(), Some(PositionError::MissingKing(Color::White)));
+        builder.place(sq("e1"), Color::White, PieceType::King).place(sq("e8"), Color::Black, PieceType::King);
+        assert!(builder.build().is_ok());
+
+        assert_eq!(builder.clone().place(sq("a1"), Color::White, PieceType::King).build().err(), Some(PositionError::TooManyKings(Color::White)));
+        assert_eq!(builder.clone().place(sq("c8"), Color::White, PieceType::Pawn).build().err(), Some(PositionError::PawnOnBackRank(sq("c8"))));
+        assert_eq!(builder.clone().castling(Color::Black, false, true).build().err(),
+            Some(PositionError::ImpossibleCastling { color: Color::Black, kingside: false }));
+        // with black to move the white king could be taken
+        assert_eq!(builder.clone().place(sq("e4"), Color::Black, PieceType::Rook).side_to_move(Color::Black).build().err(), Some(PositionError::OpponentInCheck(Color::White)));
+        assert!(builder.clone().place(sq("e4"), Color::Black, PieceType::Rook).build().is_ok());
+
+        builder.place(sq("d5"), Color::Black, PieceType::Pawn);
+        assert!(builder.clone().en_passant(Some(sq("d6"))).build().is_ok());
+        assert_eq!(builder.clone().en_passant(Some(sq("e6"))).build().err(), Some(PositionError::ImpossibleEnPassant(sq("e6"))));
+    }
+}
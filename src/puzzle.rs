@@ -0,0 +1,302 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::*;
+use crate::fen::FenError;
+
+pub const START_RATING: i32 = 1500;
+// how fast the ratings move, the usual Elo K factor
+const K_FACTOR: f64 = 32.0;
+
+#[derive(Debug)]
+pub enum PuzzleError {
+    Io(io::Error),
+    // lines are counted from 1
+    MissingField { line: usize },
+    InvalidFen { line: usize, error: FenError },
+    IllegalMove { line: usize, text: String },
+    BadRating { line: usize },
+}
+
+impl From<io::Error> for PuzzleError {
+    fn from(e: io::Error) -> PuzzleError {
+        PuzzleError::Io(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Puzzle {
+    pub id: String,
+    pub fen: String,
+    // starts with the move of the side to move, the opponent's replies are in between
+    pub solution: Vec<GameMove>,
+    pub rating: i32,
+}
+
+impl Puzzle {
+    pub fn game(&self) -> Game {
+        Game::from_fen(&self.fen).expect("puzzle positions are checked when they are read")
+    }
+
+    // "id,fen,moves,rating" with the moves in UCI, the format read by parse_puzzles
+    pub fn to_line(&self) -> String {
+        let moves: Vec<String> = self.solution.iter().map(|mv| mv.to_uci()).collect();
+        format!("{},{},{},{}", self.id, self.fen, moves.join(" "), self.rating)
+    }
+}
+
+/* one puzzle per line as "id,fen,moves,rating", the moves in UCI or SAN.
+The rating can be left out, blank lines and lines starting with # are skipped */
+pub fn parse_puzzles(text: &str) -> Result<Vec<Puzzle>, PuzzleError> {
+    let mut puzzles = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        if fields.len() < 3 || fields[2].is_empty() {
+            return Err(PuzzleError::MissingField { line: i + 1 });
+        }
+        let mut game = Game::from_fen(fields[1]).map_err(|error| PuzzleError::InvalidFen { line: i + 1, error: error })?;
+        let mut solution = vec![];
+        for text in fields[2].split_whitespace() {
+            let mv = game.parse_uci_move(text)
+                .or_else(|| game.parse_san(text))
+                .ok_or(PuzzleError::IllegalMove { line: i + 1, text: text.to_string() })?;
+            game.make_move(&mv).expect("parsed moves are legal");
+            solution.push(mv);
+        }
+        let rating = match fields.get(3) {
+            Some(rating) => rating.parse().map_err(|_| PuzzleError::BadRating { line: i + 1 })?,
+            None => START_RATING,
+        };
+        puzzles.push(Puzzle {
+            id: fields[0].to_string(),
+            fen: fields[1].to_string(),
+            solution: solution,
+            rating: rating,
+        });
+    }
+    Ok(puzzles)
+}
+
+pub fn load_puzzles<P: AsRef<Path>>(path: P) -> Result<Vec<Puzzle>, PuzzleError> {
+    parse_puzzles(&fs::read_to_string(path)?)
+}
+
+pub fn save_puzzles<P: AsRef<Path>>(path: P, puzzles: &[Puzzle]) -> Result<(), PuzzleError> {
+    let lines: Vec<String> = puzzles.iter().map(|puzzle| puzzle.to_line() + "\n").collect();
+    fs::write(path, lines.concat())?;
+    Ok(())
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Attempt {
+    // the right move, the opponent answered with the reply
+    Correct { reply: GameMove },
+    Solved,
+    Wrong,
+    // the puzzle was already solved or failed
+    Over,
+}
+
+// one try at a puzzle, the position follows the solution as it is played
+pub struct PuzzleSession {
+    puzzle: Puzzle,
+    game: Game,
+    ply: usize,
+    finished: bool,
+}
+
+impl PuzzleSession {
+    pub fn new(puzzle: Puzzle) -> PuzzleSession {
+        PuzzleSession {
+            game: puzzle.game(),
+            puzzle: puzzle,
+            ply: 0,
+            finished: false,
+        }
+    }
+
+    pub fn puzzle(&self) -> &Puzzle {
+        &self.puzzle
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // the move the player should find now
+    pub fn hint(&self) -> Option<GameMove> {
+        if self.finished { None } else { self.puzzle.solution.get(self.ply).copied() }
+    }
+
+    /* any mate is as good as the one in the solution, other moves have to
+    match it exactly. Illegal moves are wrong too */
+    pub fn try_move(&mut self, mv: &GameMove) -> Attempt {
+        if self.finished {
+            return Attempt::Over;
+        }
+        let mut after = self.game.clone();
+        if after.make_move(mv).is_err() {
+            self.finished = true;
+            return Attempt::Wrong;
+        }
+        let mates = after.legal_moves().is_empty() && after.is_checked(after.turn);
+        if !mates && self.puzzle.solution.get(self.ply) != Some(mv) {
+            self.finished = true;
+            return Attempt::Wrong;
+        }
+        self.game = after;
+        self.ply += 1;
+        match self.puzzle.solution.get(self.ply) {
+            Some(reply) if !mates => {
+                let reply = *reply;
+                self.game.make_move(&reply).expect("puzzle solutions are checked when they are read");
+                self.ply += 1;
+                if self.ply >= self.puzzle.solution.len() {
+                    // a solution should end with the player's move, but do not get stuck
+                    self.finished = true;
+                }
+                Attempt::Correct { reply: reply }
+            },
+            _ => {
+                self.finished = true;
+                Attempt::Solved
+            }
+        }
+    }
+}
+
+// the chance that a player of this rating beats one of the other rating
+fn expected_score(rating: i32, other: i32) -> f64 {
+    1.0 / (1.0 + 10f64.powf((other - rating) as f64 / 400.0))
+}
+
+// the player's rating and streaks over a set of puzzles
+pub struct PuzzleTrainer {
+    puzzles: Vec<Puzzle>,
+    done: Vec<bool>,
+    pub rating: i32,
+    pub streak: u32,
+    pub best_streak: u32,
+    pub solved: u32,
+    pub failed: u32,
+}
+
+impl PuzzleTrainer {
+    pub fn new(puzzles: Vec<Puzzle>) -> PuzzleTrainer {
+        PuzzleTrainer {
+            done: vec![false; puzzles.len()],
+            puzzles: puzzles,
+            rating: START_RATING,
+            streak: 0,
+            best_streak: 0,
+            solved: 0,
+            failed: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.puzzles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.puzzles.is_empty()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.done.iter().filter(|done| !**done).count()
+    }
+
+    // the unplayed puzzle closest to the player's rating
+    pub fn next_puzzle(&mut self) -> Option<PuzzleSession> {
+        let rating = self.rating;
+        let (i, _) = self.puzzles.iter().enumerate()
+            .filter(|(i, _)| !self.done[*i])
+            .min_by_key(|(_, puzzle)| (puzzle.rating - rating).abs())?;
+        self.done[i] = true;
+        Some(PuzzleSession::new(self.puzzles[i].clone()))
+    }
+
+    // the player's rating moves like a game against the puzzle, returns the change
+    pub fn record(&mut self, puzzle: &Puzzle, solved: bool) -> i32 {
+        let score = if solved { 1.0 } else { 0.0 };
+        let change = (K_FACTOR * (score - expected_score(self.rating, puzzle.rating))).round() as i32;
+        self.rating += change;
+        if solved {
+            self.solved += 1;
+            self.streak += 1;
+            self.best_streak = self.best_streak.max(self.streak);
+        } else {
+            self.failed += 1;
+            self.streak = 0;
+        }
+        change
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uci(game: &Game, text: &str) -> GameMove {
+        game.parse_uci_move(text).unwrap()
+    }
+
+    #[test]
+    fn reads_puzzles() {
+        let text = "# back rank\nbr1,6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1,Ra8#,1200\n\
+            fork,4k3/8/8/8/8/8/8/4K1N1 w - - 0 1,g1f3 e8d7\n";
+        let puzzles = parse_puzzles(text).unwrap();
+        assert_eq!(puzzles.len(), 2);
+        assert_eq!(puzzles[0].solution[0].to_uci(), "a1a8");
+        assert_eq!(puzzles[1].rating, START_RATING);
+        assert_eq!(parse_puzzles(&puzzles[0].to_line()).unwrap()[0], puzzles[0]);
+        assert!(matches!(parse_puzzles("x,6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1,a1a9"), Err(PuzzleError::IllegalMove { line: 1, .. })));
+    }
+
+    #[test]
+    fn plays_replies_and_accepts_other_mates() {
+        // the solution mates on a8, e8 mates just as well
+        let puzzle = Puzzle {
+            id: String::from("mate2"),
+            fen: String::from("6k1/5ppp/8/8/8/8/8/R3R1K1 w - - 0 1"),
+            solution: vec![],
+            rating: 1500,
+        };
+        let puzzle = Puzzle { solution: vec![uci(&puzzle.game(), "a1a8")], ..puzzle };
+        let mut session = PuzzleSession::new(puzzle.clone());
+        assert_eq!(session.try_move(&uci(session.game(), "e1e8")), Attempt::Solved);
+        assert_eq!(session.try_move(&uci(&puzzle.game(), "a1a8")), Attempt::Over);
+
+        let text = "p,6k1/5ppp/8/8/8/8/5PPP/R3r1K1 w - - 0 1,a1e1 g8f8 e1e8\n";
+        let puzzle = parse_puzzles(text).unwrap().remove(0);
+        let mut session = PuzzleSession::new(puzzle.clone());
+        let mv = uci(session.game(), "a1e1");
+        assert!(matches!(session.try_move(&mv), Attempt::Correct { .. }));
+        assert_eq!(session.hint().unwrap().to_uci(), "e1e8");
+        assert_eq!(session.try_move(&uci(session.game(), "e1e2")), Attempt::Wrong);
+        assert!(session.is_finished());
+    }
+
+    #[test]
+    fn tracks_rating_and_streaks() {
+        let puzzles = parse_puzzles("a,6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1,a1a8,1500\nb,6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1,a1a8,2500\n").unwrap();
+        let mut trainer = PuzzleTrainer::new(puzzles);
+        let first = trainer.next_puzzle().unwrap();
+        assert_eq!(first.puzzle().id, "a");
+        assert_eq!(trainer.record(first.puzzle(), true), 16);
+        assert_eq!(trainer.streak, 1);
+        let second = trainer.next_puzzle().unwrap();
+        assert_eq!(trainer.record(second.puzzle(), false), 0);
+        assert_eq!((trainer.streak, trainer.best_streak, trainer.rating), (0, 1, 1516));
+        assert!(trainer.next_puzzle().is_none());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::*;
+use crate::pgn::{PgnError, PgnGame};
+use crate::puzzle::Puzzle;
+use crate::search::{is_mate_score, SearchLimits, SearchResult, Searcher};
+
+#[derive(Copy, Clone, Debug)]
+pub struct ExtractOptions {
+    pub limits: SearchLimits,
+    // centipawns a move has to lose to be a blunder
+    pub blunder: i32,
+    // how far ahead the opponent has to be after it
+    pub winning: i32,
+    // how much better the solution has to be than the second best move
+    pub unique: i32,
+    // moves of the solver, the replies are not counted
+    pub max_moves: usize,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> ExtractOptions {
+        ExtractOptions {
+            limits: SearchLimits::depth(4),
+            blunder: 200,
+            winning: 200,
+            unique: 150,
+            max_moves: 3,
+        }
+    }
+}
+
+fn search_from(searcher: &mut Searcher, game: &Game, root_moves: Option<Vec<GameMove>>) -> SearchResult {
+    searcher.set_root_moves(root_moves);
+    let result = searcher.run(game);
+    searcher.set_root_moves(None);
+    result
+}
+
+/* the best move when it is clearly better than every other move, a mate
+is unique as long as the second best move does not mate too */
+fn unique_best(searcher: &mut Searcher, game: &Game, options: &ExtractOptions) -> Option<SearchResult> {
+    let best = search_from(searcher, game, None);
+    let best_move = best.best_move?;
+    let others: Vec<GameMove> = game.legal_moves().into_iter().filter(|mv| *mv != best_move).collect();
+    if others.is_empty() {
+        // a forced move is no puzzle
+        return None;
+    }
+    let second = search_from(searcher, game, Some(others));
+    let unique = if is_mate_score(best.score) && best.score > 0 {
+        !(is_mate_score(second.score) && second.score > 0)
+    } else {
+        best.score - second.score >= options.unique
+    };
+    if unique { Some(best) } else { None }
+}
+
+// the forced line from a position after a blunder, None if the first move is not unique
+fn solution(searcher: &mut Searcher, game: &Game, options: &ExtractOptions) -> Option<Vec<GameMove>> {
+    let mut game = game.clone();
+    let mut line = vec![];
+    let mut best = unique_best(searcher, &game, options);
+    while let Some(found) = best.take() {
+        let mv = found.best_move.expect("unique_best always has a move");
+        line.push(mv);
+        game.make_move(&mv).expect("the search returned an illegal move");
+        let reply = match found.pv.get(1) {
+            Some(reply) if line.len() + 1 < 2 * options.max_moves && !game.legal_moves().is_empty() => *reply,
+            _ => break,
+        };
+        // the line continues only while the solver keeps finding unique moves
+        let mut after_reply = game.clone();
+        after_reply.make_move(&reply).expect("the search returned an illegal move");
+        best = unique_best(searcher, &after_reply, options);
+        if best.is_some() {
+            line.push(reply);
+            game = after_reply;
+        }
+    }
+    if line.is_empty() { None } else { Some(line) }
+}
+
+/* replays a game and returns a puzzle for every blunder the opponent could
+punish with a unique move. Every position is searched once, plus twice for
+every solver move of a candidate to check that it is unique */
+pub fn find_puzzles(pgn_game: &PgnGame, searcher: &mut Searcher, options: &ExtractOptions, id: &str) -> Result<Vec<Puzzle>, PgnError> {
+    let mut positions = vec![pgn_game.start_position()?];
+    for (ply, mv) in pgn_game.moves.iter().enumerate() {
+        let mut next = positions.last().expect("starts with the start position").clone();
+        next.make_move(mv).map_err(|_| PgnError::UnplayableMove { game: None, ply: ply, uci: mv.to_uci() })?;
+        positions.push(next);
+    }
+
+    searcher.set_limits(options.limits);
+    // from the side to move in each position
+    let scores: Vec<i32> = positions.iter().map(|game| searcher.run(game).score).collect();
+
+    let mut puzzles = vec![];
+    for ply in 0..pgn_game.moves.len() {
+        // what the move cost its side, the score after the move is from the opponent
+        let loss = scores[ply] + scores[ply + 1];
+        if loss < options.blunder || scores[ply + 1] < options.winning {
+            continue;
+        }
+        let game = &positions[ply + 1];
+        if let Some(line) = solution(searcher, game, options) {
+            let solver_moves = line.len().div_ceil(2) as i32;
+            puzzles.push(Puzzle {
+                id: format!("{}-{}", id, ply + 1),
+                fen: game.to_fen(),
+                solution: line,
+                // longer lines are harder
+                rating: 1100 + 250 * solver_moves,
+            });
+        }
+    }
+    Ok(puzzles)
+}
+
+// puzzles from a whole PGN file, ids are "<prefix><game>-<ply>"
+pub fn extract_puzzles(games: &[PgnGame], searcher: &mut Searcher, options: &ExtractOptions, prefix: &str) -> Result<Vec<Puzzle>, PgnError> {
+    let mut puzzles = vec![];
+    for (i, game) in games.iter().enumerate() {
+        // the file index is only known here
+        let found = find_puzzles(game, searcher, options, &format!("{}{}", prefix, i + 1))
+            .map_err(|e| match e {
+                PgnError::UnplayableMove { ply, uci, .. } => PgnError::UnplayableMove { game: Some(i), ply: ply, uci: uci },
+                e => e,
+            })?;
+        puzzles.extend(found);
+    }
+    Ok(puzzles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::parse_pgn;
+
+    #[test]
+    fn finds_missed_mate_defence() {
+        let games = parse_pgn("1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0").unwrap();
+        let options = ExtractOptions { limits: SearchLimits::depth(2), ..Default::default() };
+        let mut searcher = Searcher::new(options.limits);
+        let puzzles = extract_puzzles(&games, &mut searcher, &options, "g").unwrap();
+        assert_eq!(puzzles.len(), 1);
+        assert_eq!(puzzles[0].id, "g1-6");
+        assert_eq!(puzzles[0].solution, vec![games[0].moves[6]]);
+        assert_eq!(puzzles[0].rating, 1350);
+    }
+
+    #[test]
+    fn reports_the_game_of_an_unplayable_move() {
+        let mut games = parse_pgn("1. e4 e5 1-0\n\n1. d4 d5 1-0").unwrap();
+        games[1].moves.insert(1, GameMove { from: (0, 0), to: (0, 4), promotion: None });
+        let options = ExtractOptions { limits: SearchLimits::depth(1), ..Default::default() };
+        let mut searcher = Searcher::new(options.limits);
+        let expected = PgnError::UnplayableMove { game: Some(1), ply: 1, uci: String::from("a8a4") };
+        assert_eq!(extract_puzzles(&games, &mut searcher, &options, "g").err(), Some(expected));
+    }
+
+    #[test]
+    fn solutions_need_a_unique_move() {
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let options = ExtractOptions { limits: SearchLimits::depth(2), ..Default::default() };
+        let mut searcher = Searcher::new(options.limits);
+        assert_eq!(solution(&mut searcher, &game, &options), Some(vec![game.parse_uci_move("a1a8").unwrap()]));
+        // the quiet start has no unique move
+        assert_eq!(solution(&mut searcher, &create_game(), &options), None);
+    }
+}
@@ -0,0 +1,578 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::*;
+use crate::bitbase::Bitbases;
+use crate::eval::{bitbase_score, evaluate_for_turn, piece_value};
+use crate::tt::{Bound, TranspositionTable, DEFAULT_SIZE_MB};
+
+pub const MATE: i32 = 30000;
+const INF: i32 = 32000;
+const MAX_PLY: usize = 64;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub movetime: Option<Duration>,
+    pub nodes: Option<u64>,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: u32) -> SearchLimits {
+        SearchLimits { depth: Some(depth), ..Default::default() }
+    }
+    pub fn movetime(movetime: Duration) -> SearchLimits {
+        SearchLimits { movetime: Some(movetime), ..Default::default() }
+    }
+    pub fn nodes(nodes: u64) -> SearchLimits {
+        SearchLimits { nodes: Some(nodes), ..Default::default() }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best_move: Option<GameMove>,
+    // centipawns from the side to move, mate scores are MATE - plies to mate
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+    pub pv: Vec<GameMove>,
+    pub time: Duration,
+}
+
+// called on the main search thread after every finished iteration
+pub type InfoCallback = Box<dyn FnMut(&SearchResult) + Send>;
+
+pub struct Searcher {
+    limits: SearchLimits,
+    start: Instant,
+    nodes: u64,
+    stopped: bool,
+    prev_pv: Vec<GameMove>,
+    killers: [[Option<GameMove>; 2]; MAX_PLY],
+    // indexed by from and to square, x + y*8 like the network squares
+    history: [[i32; 64]; 64],
+    tt: Arc<TranspositionTable>,
+    // shared with the helper threads and anyone who wants to stop the search
+    stop: Arc<AtomicBool>,
+    threads: usize,
+    on_info: Option<InfoCallback>,
+    bitbases: Option<Arc<Bitbases>>,
+    // only these moves are searched at the root, like UCI searchmoves
+    root_moves: Option<Vec<GameMove>>,
+}
+
+pub fn search(game: &Game, limits: SearchLimits) -> SearchResult {
+    Searcher::new(limits).run(game)
+}
+
+// bitbase wins are mates too, found at any ply and up to MAX_PLIES further away
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE - MAX_PLY as i32 - crate::bitbase::MAX_PLIES as i32
+}
+
+fn square_index(xy: (usize, usize)) -> usize {
+    xy.0 + xy.1 * 8
+}
+
+fn is_capture(game: &Game, mv: &GameMove) -> bool {
+    match game.get_content(mv.to) {
+        Content::Occupied(_) => true,
+        Content::Empty => false,
+    }
+}
+
+impl Searcher {
+    pub fn new(limits: SearchLimits) -> Searcher {
+        Searcher::with_table(limits, TranspositionTable::new(DEFAULT_SIZE_MB))
+    }
+
+    pub fn with_table(limits: SearchLimits, tt: TranspositionTable) -> Searcher {
+        let mut searcher = Searcher::new_shared(Arc::new(tt), Arc::new(AtomicBool::new(false)));
+        searcher.limits = limits;
+        searcher
+    }
+
+    // a helper shares the table and stop flag but keeps its own move ordering
+    fn helper(&self) -> Searcher {
+        let mut helper = Searcher::new_shared(self.tt.clone(), self.stop.clone());
+        helper.limits = SearchLimits { depth: self.limits.depth, ..Default::default() };
+        helper.bitbases = self.bitbases.clone();
+        helper.root_moves = self.root_moves.clone();
+        helper
+    }
+
+    fn new_shared(tt: Arc<TranspositionTable>, stop: Arc<AtomicBool>) -> Searcher {
+        Searcher {
+            limits: SearchLimits::default(),
+            start: Instant::now(),
+            nodes: 0,
+            stopped: false,
+            prev_pv: vec![],
+            killers: [[None; 2]; MAX_PLY],
+            history: [[0; 64]; 64],
+            tt: tt,
+            stop: stop,
+            threads: 1,
+            on_info: None,
+            bitbases: None,
+            root_moves: None,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: SearchLimits) {
+        self.limits = limits;
+    }
+
+    // 1 thread is the default and searches deterministically
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn table(&self) -> &Arc<TranspositionTable> {
+        &self.tt
+    }
+
+    // only possible while no search is running
+    pub fn set_table(&mut self, tt: TranspositionTable) {
+        self.tt = Arc::new(tt);
+    }
+
+    pub fn set_info_callback(&mut self, on_info: Option<InfoCallback>) {
+        self.on_info = on_info;
+    }
+
+    pub fn set_bitbases(&mut self, bitbases: Option<Arc<Bitbases>>) {
+        self.bitbases = bitbases;
+    }
+
+    pub fn set_root_moves(&mut self, root_moves: Option<Vec<GameMove>>) {
+        self.root_moves = root_moves;
+    }
+
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /* the stop flag is cleared when the search returns, not when it
+    starts, so a stop sent just before the search thread gets going
+    is not lost */
+    pub fn run(&mut self, game: &Game) -> SearchResult {
+        // the copies made while searching should not carry the event log
+        let game = &game.detached();
+        self.tt.new_search();
+        if self.threads <= 1 {
+            let result = self.iterate(game, 1);
+            self.stop.store(false, Ordering::Relaxed);
+            return result;
+        }
+
+        // LAZY SMP: helpers search the same position and fill the shared table
+        let mut helpers: Vec<Searcher> = (1..self.threads).map(|_| self.helper()).collect();
+        let (mut result, helper_nodes) = thread::scope(|scope| {
+            let handles: Vec<_> = helpers.iter_mut().enumerate()
+                .map(|(i, helper)| scope.spawn(move || helper.iterate(game, 1 + (i % 2) as u32).nodes))
+                .collect();
+            let result = self.iterate(game, 1);
+            self.stop.store(true, Ordering::Relaxed);
+            let helper_nodes: u64 = handles.into_iter()
+                .map(|handle| handle.join().expect("search helper thread panicked"))
+                .sum();
+            (result, helper_nodes)
+        });
+        self.stop.store(false, Ordering::Relaxed);
+        result.nodes += helper_nodes;
+        result
+    }
+
+    fn iterate(&mut self, game: &Game, start_depth: u32) -> SearchResult {
+        self.start = Instant::now();
+        self.nodes = 0;
+        self.stopped = false;
+        self.prev_pv.clear();
+        // move ordering starts over, so only the table carries over between searches
+        self.killers = [[None; 2]; MAX_PLY];
+        self.history = [[0; 64]; 64];
+
+        let mut root_moves = game.legal_moves();
+        if let Some(allowed) = &self.root_moves {
+            root_moves.retain(|mv| allowed.contains(mv));
+        }
+        let mut result = SearchResult {
+            best_move: root_moves.first().copied(),
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            pv: vec![],
+            time: Duration::ZERO,
+        };
+        if root_moves.is_empty() {
+            result.score = if game.is_checked(game.get_turn()) && self.root_moves.is_none() { -MATE } else { 0 };
+            return result;
+        }
+
+        let max_depth = self.limits.depth.unwrap_or(MAX_PLY as u32 - 1).min(MAX_PLY as u32 - 1);
+        for depth in start_depth.min(max_depth)..=max_depth {
+            let mut pv = vec![];
+            let score = self.negamax(game, depth, 0, -INF, INF, &mut pv);
+            // an interrupted iteration is only trusted for its first move
+            if self.stopped {
+                if depth == 1 && !pv.is_empty() {
+                    result.best_move = Some(pv[0]);
+                    result.pv = pv;
+                }
+                break;
+            }
+            result.best_move = pv.first().copied();
+            result.score = score;
+            result.depth = depth;
+            result.pv = pv.clone();
+            result.nodes = self.nodes;
+            result.time = self.start.elapsed();
+            self.prev_pv = pv;
+            if let Some(on_info) = self.on_info.as_mut() {
+                on_info(&result);
+            }
+
+            if is_mate_score(score) {
+                break;
+            }
+        }
+        result.nodes = self.nodes;
+        result.time = self.start.elapsed();
+        result
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+        if self.stop.load(Ordering::Relaxed) {
+            self.stopped = true;
+        }
+        if let Some(max_nodes) = self.limits.nodes {
+            if self.nodes >= max_nodes {
+                self.stopped = true;
+            }
+        }
+        if let Some(movetime) = self.limits.movetime {
+            if self.nodes.is_multiple_of(256) && self.start.elapsed() >= movetime {
+                self.stopped = true;
+            }
+        }
+        self.stopped
+    }
+
+    fn negamax(&mut self, game: &Game, depth: u32, ply: usize, mut alpha: i32, beta: i32, pv: &mut Vec<GameMove>) -> i32 {
+        if self.should_stop() {
+            return 0;
+        }
+        // REPETITION, a position seen before is scored as a draw
+        if ply > 0 && game.repetition_count() > 0 {
+            return 0;
+        }
+        // BITBASES, small endgames are looked up instead of searched
+        if ply > 0 {
+            if let Some(score) = self.bitbases.as_ref().and_then(|bitbases| bitbase_score(bitbases, game, ply)) {
+                return score;
+            }
+        }
+        let in_check = game.is_checked(game.get_turn());
+        // CHECK EXTENSION
+        let depth = if in_check { depth + 1 } else { depth };
+        if depth == 0 || ply >= MAX_PLY - 1 {
+            return self.quiescence(game, ply, alpha, beta);
+        }
+        self.nodes += 1;
+
+        // TRANSPOSITION TABLE
+        let key = game.hash();
+        let mut tt_move = None;
+        if let Some(entry) = self.tt.probe(key, ply) {
+            tt_move = entry.best_move;
+            if ply > 0 && entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return entry.score,
+                    Bound::Lower if entry.score >= beta => return entry.score,
+                    Bound::Upper if entry.score <= alpha => return entry.score,
+                    _ => (),
+                }
+            }
+        }
+
+        let mut moves = game.legal_moves();
+        if moves.is_empty() {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+        let restricted = ply == 0 && self.root_moves.is_some();
+        if restricted {
+            let allowed = self.root_moves.as_ref().expect("checked above");
+            moves.retain(|mv| allowed.contains(mv));
+        }
+        self.order_moves(game, &mut moves, ply, tt_move);
+
+        let original_alpha = alpha;
+        let mut best = -INF;
+        let mut best_move = None;
+        for mv in moves {
+            let mut child = game.clone();
+            child.make_move(&mv).expect("legal_moves returned an illegal move");
+            let mut child_pv = vec![];
+            let score = -self.negamax(&child, depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+                best_move = Some(mv);
+            }
+            if score > alpha {
+                alpha = score;
+                pv.clear();
+                pv.push(mv);
+                pv.extend(child_pv);
+            }
+            if alpha >= beta {
+                if !is_capture(game, &mv) && mv.promotion.is_none() {
+                    if self.killers[ply][0] != Some(mv) {
+                        self.killers[ply][1] = self.killers[ply][0];
+                        self.killers[ply][0] = Some(mv);
+                    }
+                    self.history[square_index(mv.from)][square_index(mv.to)] += (depth * depth) as i32;
+                }
+                break;
+            }
+        }
+
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if alpha > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        // a score over some of the root moves is not the score of the position
+        if !restricted {
+            self.tt.store(key, depth, bound, best, best_move, ply);
+        }
+        best
+    }
+
+    fn quiescence(&mut self, game: &Game, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        if self.should_stop() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        let moves = game.legal_moves();
+        if moves.is_empty() {
+            return if game.is_checked(game.get_turn()) { -MATE + ply as i32 } else { 0 };
+        }
+
+        let stand_pat = evaluate_for_turn(game);
+        if stand_pat >= beta || ply >= MAX_PLY - 1 {
+            return stand_pat;
+        }
+        if stand_pat > alpha {
+            alpha = stand_pat;
+        }
+
+        // captures that lose material in the exchange are not worth looking at
+        let mut captures: Vec<GameMove> = moves.into_iter()
+            .filter(|mv| mv.promotion == Some(PieceType::Queen) || (is_capture(game, mv) && game.is_safe_capture(mv)))
+            .collect();
+        self.order_moves(game, &mut captures, ply, None);
+
+        for mv in captures {
+            let mut child = game.clone();
+            child.make_move(&mv).expect("legal_moves returned an illegal move");
+            let score = -self.quiescence(&child, ply + 1, -beta, -alpha);
+            if self.stopped {
+                return 0;
+            }
+            if score >= beta {
+                return score;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+        }
+        alpha
+    }
+
+    fn order_moves(&self, game: &Game, moves: &mut [GameMove], ply: usize, tt_move: Option<GameMove>) {
+        let pv_move = self.prev_pv.get(ply).copied();
+        moves.sort_by_cached_key(|mv| -self.move_score(game, mv, ply, pv_move, tt_move));
+    }
+
+    fn move_score(&self, game: &Game, mv: &GameMove, ply: usize, pv_move: Option<GameMove>, tt_move: Option<GameMove>) -> i32 {
+        if tt_move == Some(*mv) {
+            return 2_000_000;
+        }
+        if pv_move == Some(*mv) {
+            return 1_000_000;
+        }
+        let mut score = 0;
+        // MVV-LVA
+        if let Content::Occupied(victim) = game.get_content(mv.to) {
+            let attacker = match game.get_content(mv.from) {
+                Content::Occupied(p) if p.piece_type == PieceType::King => 1000,
+                Content::Occupied(p) => piece_value(p.piece_type),
+                Content::Empty => 0,
+            };
+            // SEE, losing captures go after the killers
+            let see = game.see(mv);
+            score += if see >= 0 {
+                100_000 + 10 * piece_value(victim.piece_type) - attacker
+            } else {
+                65_000 + see / 10
+            };
+        }
+        if let Some(new_piece_type) = mv.promotion {
+            score += 90_000 + piece_value(new_piece_type);
+        }
+        if score > 0 {
+            return score;
+        }
+        if self.killers[ply][0] == Some(*mv) {
+            return 80_000;
+        }
+        if self.killers[ply][1] == Some(*mv) {
+            return 70_000;
+        }
+        self.history[square_index(mv.from)][square_index(mv.to)].min(60_000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_scholars_mate() {
+        let mut game = create_game();
+        game.move_from_to((4, 6), (4, 4));
+        game.move_from_to((4, 1), (4, 3));
+        game.move_from_to((5, 7), (2, 4));
+        game.move_from_to((1, 0), (2, 2));
+        game.move_from_to((3, 7), (7, 3));
+        game.move_from_to((6, 0), (5, 2));
+
+        let result = search(&game, SearchLimits::depth(2));
+        assert_eq!(result.best_move, Some(GameMove { from: (7, 3), to: (5, 1), promotion: None }));
+        assert_eq!(result.score, MATE - 1);
+    }
+
+    #[test]
+    fn takes_hanging_queen() {
+        let mut game = create_game();
+        game.move_from_to((4, 6), (4, 4));
+        game.move_from_to((3, 1), (3, 3));
+        game.move_from_to((3, 7), (6, 4));
+
+        let result = search(&game, SearchLimits::depth(2));
+        assert_eq!(result.best_move, Some(GameMove { from: (2, 0), to: (6, 4), promotion: None }));
+        assert_eq!(result.pv[0], result.best_move.unwrap());
+        assert!(result.score > 500);
+    }
+
+    #[test]
+    fn reuses_table_between_searches() {
+        let mut game = create_game();
+        game.move_from_to((4, 6), (4, 4));
+        game.move_from_to((3, 1), (3, 3));
+        game.move_from_to((3, 7), (6, 4));
+
+        let mut searcher = Searcher::new(SearchLimits::depth(3));
+        let first = searcher.run(&game);
+        let second = searcher.run(&game);
+        assert_eq!(first.best_move, second.best_move);
+        assert!(second.nodes < first.nodes);
+    }
+
+    #[test]
+    fn single_thread_is_deterministic() {
+        let mut game = create_game();
+        game.move_from_to((4, 6), (4, 4));
+        game.move_from_to((4, 1), (4, 3));
+
+        // the same searcher twice, with the table cleared in between
+        let mut searcher = Searcher::new(SearchLimits::depth(3));
+        searcher.set_threads(1);
+        let first = searcher.run(&game);
+        searcher.table().clear();
+        let second = searcher.run(&game);
+        assert_eq!(first.pv, second.pv);
+        assert_eq!(first.score, second.score);
+        assert_eq!(first.nodes, second.nodes);
+    }
+
+    #[test]
+    fn threads_find_mate() {
+        let mut game = create_game();
+        game.move_from_to((4, 6), (4, 4));
+        game.move_from_to((4, 1), (4, 3));
+        game.move_from_to((5, 7), (2, 4));
+        game.move_from_to((1, 0), (2, 2));
+        game.move_from_to((3, 7), (7, 3));
+        game.move_from_to((6, 0), (5, 2));
+
+        let mut searcher = Searcher::new(SearchLimits::depth(3));
+        searcher.set_threads(4);
+        let result = searcher.run(&game);
+        assert_eq!(result.best_move, Some(GameMove { from: (7, 3), to: (5, 1), promotion: None }));
+        assert_eq!(result.score, MATE - 1);
+    }
+
+    #[test]
+    fn respects_node_limit() {
+        let game = create_game();
+        let result = search(&game, SearchLimits::nodes(500));
+        assert!(result.best_move.is_some());
+        assert!(result.nodes <= 500);
+    }
+
+    #[test]
+    fn scores_endgames_from_bitbases() {
+        let mut bitbases = Bitbases::new();
+        bitbases.generate(&crate::bitbase::Material::parse("KRK").unwrap());
+        let game = Game::from_fen("8/8/8/4k3/8/8/8/K6R w - - 0 1").unwrap();
+        let plies = bitbases.probe(&game).unwrap().plies_to_mate.unwrap() as i32;
+        // a probe deep in the tree still counts as a mate
+        assert!(is_mate_score(bitbase_score(&bitbases, &game, MAX_PLY - 1).unwrap()));
+
+        let mut searcher = Searcher::new(SearchLimits::depth(2));
+        searcher.set_bitbases(Some(Arc::new(bitbases)));
+        let result = searcher.run(&game);
+        assert_eq!(result.score, MATE - plies);
+    }
+
+    #[test]
+    fn searches_only_root_moves() {
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let quiet = game.parse_uci_move("g1f1").unwrap();
+        let mut searcher = Searcher::new(SearchLimits::depth(2));
+        searcher.set_root_moves(Some(vec![quiet]));
+        let result = searcher.run(&game);
+        assert_eq!(result.best_move, Some(quiet));
+        assert!(!is_mate_score(result.score));
+        searcher.set_root_moves(None);
+        assert_eq!(searcher.run(&game).score, MATE - 1);
+    }
+
+    #[test]
+    fn no_move_when_mated() {
+        let mut game = create_game();
+        game.move_from_to((5, 6), (5, 5));
+        game.move_from_to((4, 1), (4, 3));
+        game.move_from_to((6, 6), (6, 4));
+        game.move_from_to((3, 0), (7, 4));
+
+        let result = search(&game, SearchLimits::depth(3));
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, -MATE);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::*;
+use crate::eval::piece_value;
+use crate::threats::{attackers_on, other, piece_on};
+
+// the king is worth more than anything it could win
+fn see_value(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::King => 20000,
+        piece_type => piece_value(piece_type),
+    }
+}
+
+// the cheapest piece of a colour attacking xy and its square
+fn least_valuable_attacker(board: &Board, xy: (usize, usize), color: Color) -> Option<((usize, usize), Piece)> {
+    attackers_on(board, xy, color).into_iter()
+        .filter_map(|square| piece_on(board, square).map(|p| (square, p)))
+        .min_by_key(|(_, p)| see_value(p.piece_type))
+}
+
+/* SWAP LIST: both sides keep taking on the square with their cheapest piece
+and either side may stop when going on would lose. Taking a piece off the
+board uncovers the sliders behind it, so x-rays count as attackers */
+fn exchange(mut board: Board, mv: &GameMove) -> i32 {
+    let mover = match piece_on(&board, mv.from) {
+        Some(p) => p,
+        None => return 0,
+    };
+    let mut gain = vec![piece_on(&board, mv.to).map_or(0, |p| see_value(p.piece_type))];
+    // the piece that stands on the square and can be taken next
+    let mut on_square = see_value(mv.promotion.unwrap_or(mover.piece_type));
+    if let Some(new_piece_type) = mv.promotion {
+        gain[0] += see_value(new_piece_type) - see_value(PieceType::Pawn);
+    }
+    board[mv.from.1][mv.from.0] = Content::Empty;
+    board[mv.to.1][mv.to.0] = Content::Occupied(mover);
+
+    let mut side = other(mover.color);
+    while let Some((square, p)) = least_valuable_attacker(&board, mv.to, side) {
+        // the king can not take into a defended square
+        if p.piece_type == PieceType::King && !attackers_on(&board, mv.to, other(side)).is_empty() {
+            break;
+        }
+        let previous = *gain.last().expect("gain starts with the first capture");
+        gain.push(on_square - previous);
+        on_square = see_value(p.piece_type);
+        board[square.1][square.0] = Content::Empty;
+        board[mv.to.1][mv.to.0] = Content::Occupied(p);
+        side = other(side);
+    }
+
+    // each side only goes on with the exchange if it pays
+    while gain.len() > 1 {
+        let last = gain.pop().expect("checked the length");
+        let previous = gain.last_mut().expect("checked the length");
+        *previous = -(-*previous).max(last);
+    }
+    gain[0]
+}
+
+impl Game {
+    /* the material the mover wins with a capture once the exchange on the
+    target square is over, negative when the capture loses material */
+    pub fn see(&self, mv: &GameMove) -> i32 {
+        exchange(self.board, mv)
+    }
+
+    // the same for the cheapest capture of the piece on xy by a colour, 0 when nothing can take it
+    pub fn see_square(&self, xy: (usize, usize), color: Color) -> i32 {
+        match least_valuable_attacker(&self.board, xy, color) {
+            Some((from, _)) => self.see(&GameMove { from: from, to: xy, promotion: None }),
+            None => 0,
+        }
+    }
+
+    pub fn is_safe_capture(&self, mv: &GameMove) -> bool {
+        self.see(mv) >= 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn see(fen: &str, uci: &str) -> i32 {
+        let game = Game::from_fen(fen).unwrap();
+        game.see(&game.parse_uci_move(uci).unwrap())
+    }
+
+    #[test]
+    fn simple_exchanges() {
+        // a free pawn, a defended pawn and a queen that takes a defended pawn
+        assert_eq!(see("4k3/8/8/3p4/8/8/8/3RK3 w - - 0 1", "d1d5"), 100);
+        assert_eq!(see("4k3/8/4p3/3p4/8/8/8/3RK3 w - - 0 1", "d1d5"), 100 - 500);
+        assert_eq!(see("4k3/8/4p3/3p4/4P3/8/8/3QK3 w - - 0 1", "e4d5"), 100);
+        // the king may only take when nothing defends the square
+        assert_eq!(see("8/8/8/3pk3/4P3/8/8/4K3 w - - 0 1", "e4d5"), 0);
+        assert_eq!(see("8/8/8/3pk3/4P3/8/8/3QK3 w - - 0 1", "e4d5"), 100);
+    }
+
+    #[test]
+    fn x_rays() {
+        // doubled rooks on both sides, the rook behind joins in after the first one is gone
+        assert_eq!(see("3rk3/3r4/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5"), 100 - 500);
+        assert_eq!(see("3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5"), 100);
+        // the bishop behind the queen defends b7 through it
+        assert_eq!(see("1r2k3/1p6/8/8/4Q3/8/6B1/4K3 w - - 0 1", "e4b7"), 100 - 950 + 500);
+        let game = Game::from_fen("4k3/4r3/8/3p4/8/8/3R4/3RK3 w - - 0 1").unwrap();
+        assert_eq!(game.see_square(crate::fen::parse_square("d5").unwrap(), Color::White), 100);
+    }
+}
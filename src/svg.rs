@@ -0,0 +1,245 @@
+use crate::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SvgOptions {
+    // width and height of the board in pixels
+    pub size: u32,
+    pub light: String,
+    pub dark: String,
+    // file letters and rank numbers in the corners of the edge squares
+    pub coordinates: bool,
+    // seen from black
+    pub flipped: bool,
+    pub highlights: Vec<(usize, usize)>,
+    pub highlight_color: String,
+    // from square to square, drawn over the pieces
+    pub arrows: Vec<((usize, usize), (usize, usize))>,
+    pub arrow_color: String,
+}
+
+impl Default for SvgOptions {
+    fn default() -> SvgOptions {
+        SvgOptions {
+            size: 400,
+            light: String::from("#f0d9b5"),
+            dark: String::from("#b58863"),
+            coordinates: true,
+            flipped: false,
+            highlights: vec![],
+            highlight_color: String::from("#cdd26a"),
+            arrows: vec![],
+            arrow_color: String::from("#15781b"),
+        }
+    }
+}
+
+/* the pieces are drawn in a 45 by 45 box. DETAIL is the colour of the lines
+drawn on top of the body, so they show on both colours */
+fn piece_shape(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::Pawn => concat!(
+            r#"<circle cx="22.5" cy="13" r="5"/>"#,
+            r#"<path d="M 16 35 L 18.5 20 L 26.5 20 L 29 35 Z"/>"#,
+            r#"<path d="M 11 39 L 34 39 L 32 35 L 13 35 Z"/>"#,
+        ),
+        PieceType::Knight => concat!(
+            r#"<path d="M 12 39 L 33 39 L 33 35 L 12 35 Z"/>"#,
+            r#"<path d="M 14 35 C 14 27 19 24 20 20 C 17 22 13 24 10 22 C 9 19 14 14 17 11 L 18 7 L 21 10 C 28 10 33 16 32 35 Z"/>"#,
+            r#"<circle cx="17.5" cy="14.5" r="1.2" fill="DETAIL" stroke="none"/>"#,
+        ),
+        PieceType::Bishop => concat!(
+            r#"<path d="M 10 39 L 35 39 L 33 35 L 12 35 Z"/>"#,
+            r#"<path d="M 15 35 C 13 27 17 19 22.5 14 C 28 19 32 27 30 35 Z"/>"#,
+            r#"<circle cx="22.5" cy="10" r="3"/>"#,
+            r#"<path d="M 20 25 L 25 25 M 22.5 22.5 L 22.5 27.5" fill="none" stroke="DETAIL"/>"#,
+        ),
+        PieceType::Rook => concat!(
+            r#"<path d="M 12 39 L 33 39 L 33 35 L 12 35 Z"/>"#,
+            r#"<path d="M 15 35 L 16 17 L 29 17 L 30 35 Z"/>"#,
+            r#"<path d="M 12 17 L 12 9 L 16 9 L 16 12 L 20 12 L 20 9 L 25 9 L 25 12 L 29 12 L 29 9 L 33 9 L 33 17 Z"/>"#,
+        ),
+        PieceType::Queen => concat!(
+            r#"<path d="M 10 39 L 35 39 L 33 35 L 12 35 Z"/>"#,
+            r#"<path d="M 12 35 L 8 14 L 14 25 L 15 11 L 20 24 L 22.5 9 L 25 24 L 30 11 L 31 25 L 37 14 L 33 35 Z"/>"#,
+            r#"<circle cx="8" cy="14" r="2"/><circle cx="15" cy="11" r="2"/><circle cx="22.5" cy="9" r="2"/>"#,
+            r#"<circle cx="30" cy="11" r="2"/><circle cx="37" cy="14" r="2"/>"#,
+        ),
+        PieceType::King => concat!(
+            r#"<path d="M 10 39 L 35 39 L 33 35 L 12 35 Z"/>"#,
+            r#"<path d="M 12 35 C 6 27 10 19 17 21 C 19 17 26 17 28 21 C 35 19 39 27 33 35 Z"/>"#,
+            r#"<path d="M 22.5 6 L 22.5 17 M 18 10.5 L 27 10.5" fill="none"/>"#,
+            r#"<path d="M 22.5 21 L 22.5 33" fill="none" stroke="DETAIL"/>"#,
+        ),
+    }
+}
+
+fn piece_id(p: Piece) -> String {
+    let color = match p.color { Color::White => 'w', Color::Black => 'b' };
+    format!("{}{}", color, fen::piece_char(Piece { color: Color::White, ..p }))
+}
+
+fn piece_def(p: Piece) -> String {
+    let (fill, detail) = match p.color {
+        Color::White => ("#ffffff", "#000000"),
+        Color::Black => ("#000000", "#ffffff"),
+    };
+    format!(r##"<g id="{}" fill="{}" stroke="#000000" stroke-width="1.5" stroke-linejoin="round">{}</g>"##,
+        piece_id(p), fill, piece_shape(p.piece_type).replace("DETAIL", detail))
+}
+
+impl Game {
+    /* the position as a standalone SVG document. Only the pieces on the board
+    are defined, each once, and placed with <use> */
+    pub fn to_svg(&self, options: &SvgOptions) -> String {
+        let square = options.size as f32 / BOARD_SIZE as f32;
+        // top left corner of a square on the picture
+        let corner = |xy: (usize, usize)| {
+            let (col, row) = if options.flipped { (BOARD_SIZE - 1 - xy.0, BOARD_SIZE - 1 - xy.1) } else { xy };
+            (col as f32 * square, row as f32 * square)
+        };
+        let center = |xy: (usize, usize)| {
+            let (x, y) = corner(xy);
+            (x + square / 2.0, y + square / 2.0)
+        };
+
+        let mut pieces = vec![];
+        for y in 0..BOARD_SIZE {
+            for x in 0..BOARD_SIZE {
+                if let Content::Occupied(p) = self.board[y][x] {
+                    pieces.push(((x, y), p));
+                }
+            }
+        }
+
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{0}" height="{0}" viewBox="0 0 {0} {0}">"#,
+            options.size);
+        svg += "\n<defs>\n";
+        let mut defined: Vec<String> = vec![];
+        for (_, p) in pieces.iter() {
+            if !defined.contains(&piece_id(*p)) {
+                defined.push(piece_id(*p));
+                svg += &piece_def(*p);
+                svg += "\n";
+            }
+        }
+        if !options.arrows.is_empty() {
+            svg += &format!(
+                r#"<marker id="arrowhead" viewBox="0 0 10 10" refX="2" refY="5" markerWidth="3" markerHeight="3" orient="auto"><path d="M 0 0 L 10 5 L 0 10 Z" fill="{}"/></marker>"#,
+                options.arrow_color);
+            svg += "\n";
+        }
+        svg += "</defs>\n";
+
+        // SQUARES
+        for y in 0..BOARD_SIZE {
+            for x in 0..BOARD_SIZE {
+                let (left, top) = corner((x, y));
+                let fill = if (x + y) % 2 == 0 { &options.light } else { &options.dark };
+                svg += &format!(r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#, left, top, square, square, fill);
+                svg += "\n";
+            }
+        }
+        for &xy in options.highlights.iter() {
+            let (left, top) = corner(xy);
+            svg += &format!(r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" fill-opacity="0.6"/>"#,
+                left, top, square, square, options.highlight_color);
+            svg += "\n";
+        }
+
+        // COORDINATES in the colour of the other squares, along the bottom and the left edge
+        if options.coordinates {
+            let font = square / 5.0;
+            let bottom = if options.flipped { 0 } else { BOARD_SIZE - 1 };
+            let left = if options.flipped { BOARD_SIZE - 1 } else { 0 };
+            for i in 0..BOARD_SIZE {
+                let file_square = (i, bottom);
+                let (x, y) = corner(file_square);
+                let fill = if (file_square.0 + file_square.1) % 2 == 0 { &options.dark } else { &options.light };
+                svg += &format!(r#"<text x="{}" y="{}" font-family="sans-serif" font-size="{}" font-weight="bold" fill="{}" text-anchor="end">{}</text>"#,
+                    x + square - font / 4.0, y + square - font / 4.0, font, fill, (b'a' + i as u8) as char);
+                svg += "\n";
+
+                let rank_square = (left, i);
+                let (x, y) = corner(rank_square);
+                let fill = if (rank_square.0 + rank_square.1) % 2 == 0 { &options.dark } else { &options.light };
+                svg += &format!(r#"<text x="{}" y="{}" font-family="sans-serif" font-size="{}" font-weight="bold" fill="{}">{}</text>"#,
+                    x + font / 4.0, y + font, font, fill, BOARD_SIZE - i);
+                svg += "\n";
+            }
+        }
+
+        // PIECES
+        let scale = square / 45.0;
+        for (xy, p) in pieces.iter() {
+            let (left, top) = corner(*xy);
+            svg += &format!(r##"<use xlink:href="#{}" transform="translate({},{}) scale({})"/>"##, piece_id(*p), left, top, scale);
+            svg += "\n";
+        }
+
+        // ARROWS end a bit before the middle of the square so the head sits on it
+        for &(from, to) in options.arrows.iter() {
+            let (x1, y1) = center(from);
+            let (x2, y2) = center(to);
+            let length = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+            if length == 0.0 {
+                continue;
+            }
+            let shorten = square * 0.3;
+            let (x2, y2) = (x2 - (x2 - x1) / length * shorten, y2 - (y2 - y1) / length * shorten);
+            svg += &format!(r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}" stroke-opacity="0.8" stroke-linecap="round" marker-end="url(#arrowhead)"/>"#,
+                x1, y1, x2, y2, options.arrow_color, square / 6.0);
+            svg += "\n";
+        }
+        svg += "</svg>\n";
+        svg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::parse_square;
+
+    fn sq(name: &str) -> (usize, usize) {
+        parse_square(name).unwrap()
+    }
+
+    #[test]
+    fn start_position() {
+        let svg = create_game().to_svg(&SvgOptions::default());
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert!(svg.contains(r#"viewBox="0 0 400 400""#));
+        assert_eq!(svg.matches("<use ").count(), 32);
+        // one definition per kind of piece
+        assert_eq!(svg.matches("<g id=").count(), 12);
+        assert_eq!(svg.matches("<rect ").count(), 64);
+        assert_eq!(svg.matches("<text ").count(), 16);
+        assert!(svg.contains(r##"<use xlink:href="#wK" transform="translate(200,350) scale(1.1111112)"/>"##));
+        assert!(!svg.contains("DETAIL"));
+    }
+
+    #[test]
+    fn flipped_with_highlights_and_arrows() {
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let options = SvgOptions {
+            size: 240,
+            coordinates: false,
+            flipped: true,
+            highlights: vec![sq("e1")],
+            arrows: vec![(sq("e1"), sq("e2")), (sq("a1"), sq("a1"))],
+            ..Default::default()
+        };
+        let svg = game.to_svg(&options);
+        assert_eq!(svg.matches("<g id=").count(), 2);
+        assert!(!svg.contains("<text "));
+        // e1 is in the top row, fourth from the left
+        assert!(svg.contains(r##"<use xlink:href="#wK" transform="translate(90,0)"##));
+        assert!(svg.contains(r##"<rect x="90" y="0" width="30" height="30" fill="#cdd26a""##));
+        // the arrow to nowhere is left out
+        assert_eq!(svg.matches("<line ").count(), 1);
+        assert!(svg.contains(r#"<line x1="105" y1="15" x2="105" y2="36""#));
+        assert!(svg.contains(r#"<marker id="arrowhead""#));
+    }
+}
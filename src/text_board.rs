@@ -0,0 +1,117 @@
+use crate::*;
+use crate::fen::piece_char;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextBoardOptions {
+    // chess glyphs instead of FEN letters
+    pub unicode: bool,
+    // files and ranks around the board
+    pub coordinates: bool,
+    // seen from black, rank 1 on top and the h-file on the left
+    pub flipped: bool,
+    // drawn in brackets, e.g. the last move
+    pub highlights: Vec<(usize, usize)>,
+    // side to move, castling rights and the move counters under the board
+    pub side_info: bool,
+}
+
+impl Default for TextBoardOptions {
+    fn default() -> TextBoardOptions {
+        TextBoardOptions {
+            unicode: false,
+            coordinates: true,
+            flipped: false,
+            highlights: vec![],
+            side_info: false,
+        }
+    }
+}
+
+fn glyph(content: Content, unicode: bool) -> String {
+    match (content, unicode) {
+        (Content::Empty, false) => String::from("."),
+        (Content::Empty, true) => String::from("·"),
+        (Content::Occupied(p), false) => piece_char(p).to_string(),
+        (Content::Occupied(_), true) => content.get_symbol().to_string(),
+    }
+}
+
+impl Game {
+    /* the board as text, one line per rank:
+            a  b  c  d  e  f  g  h
+         8  r  n  b  q  k  b  n  r  8
+       with a highlighted square drawn as [r] */
+    pub fn to_text(&self, options: &TextBoardOptions) -> String {
+        let order: Vec<usize> = if options.flipped { (0..BOARD_SIZE).rev().collect() } else { (0..BOARD_SIZE).collect() };
+        let files: String = order.iter().map(|&x| format!(" {} ", (b'a' + x as u8) as char)).collect();
+
+        let mut lines = vec![];
+        if options.coordinates {
+            lines.push(format!("  {}", files).trim_end().to_string());
+        }
+        for &y in order.iter() {
+            let mut line = if options.coordinates { format!("{} ", BOARD_SIZE - y) } else { String::new() };
+            for &x in order.iter() {
+                let square = glyph(self.board[y][x], options.unicode);
+                if options.highlights.contains(&(x, y)) {
+                    line += &format!("[{}]", square);
+                } else {
+                    line += &format!(" {} ", square);
+                }
+            }
+            if options.coordinates {
+                line += &format!(" {}", BOARD_SIZE - y);
+            }
+            lines.push(line.trim_end().to_string());
+        }
+        if options.coordinates {
+            lines.push(format!("  {}", files).trim_end().to_string());
+        }
+
+        if options.side_info {
+            let turn = match self.turn { Color::White => "White", Color::Black => "Black" };
+            let check = if self.is_checked(self.turn) { ", in check" } else { "" };
+            lines.push(format!("{} to move{}", turn, check));
+            lines.push(format!("Castling: {}", self.castling_field()));
+            lines.push(format!("Halfmove clock: {}, move {}", self.halfmove_clock, self.fullmove_number));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_from_both_sides() {
+        let game = create_game();
+        let text = game.to_text(&TextBoardOptions::default());
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0], "   a  b  c  d  e  f  g  h");
+        assert_eq!(lines[1], "8  r  n  b  q  k  b  n  r  8");
+        assert_eq!(lines[5], "4  .  .  .  .  .  .  .  .  4");
+        assert_eq!(lines[8], "1  R  N  B  Q  K  B  N  R  1");
+
+        let flipped = game.to_text(&TextBoardOptions { flipped: true, coordinates: false, ..Default::default() });
+        assert_eq!(flipped.lines().next(), Some(" R  N  B  K  Q  B  N  R"));
+        assert!(game.to_text(&TextBoardOptions { unicode: true, ..Default::default() }).contains("♜  ♞  ♝  ♛  ♚"));
+    }
+
+    #[test]
+    fn highlights_and_side_info() {
+        let mut game = create_game();
+        game.make_move(&game.parse_uci_move("e2e4").unwrap()).unwrap();
+        let options = TextBoardOptions {
+            highlights: vec![(4, 6), (4, 4), (5, 4)],
+            side_info: true,
+            ..Default::default()
+        };
+        let text = game.to_text(&options);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[5], "4  .  .  .  . [P][.] .  .  4");
+        assert_eq!(lines[7], "2  P  P  P  P [.] P  P  P  2");
+        assert_eq!(&lines[10..], ["Black to move", "Castling: KQkq", "Halfmove clock: 0, move 1"]);
+    }
+}
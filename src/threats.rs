@@ -0,0 +1,247 @@
+use crate::*;
+use crate::eval::piece_value;
+
+const KNIGHT_JUMPS: [(i32, i32); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+const KING_STEPS: [(i32, i32); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HangingPiece {
+    pub square: (usize, usize),
+    pub piece: Piece,
+    pub attackers: Vec<(usize, usize)>,
+    pub defenders: Vec<(usize, usize)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pin {
+    pub pinned: (usize, usize),
+    pub pinner: (usize, usize),
+    // from the pinner up to the king, the pinner included, the pinned piece may only move here
+    pub line: Vec<(usize, usize)>,
+}
+
+fn offset(xy: (usize, usize), dx: i32, dy: i32) -> Option<(usize, usize)> {
+    let x = xy.0 as i32 + dx;
+    let y = xy.1 as i32 + dy;
+    if (0..BOARD_SIZE as i32).contains(&x) && (0..BOARD_SIZE as i32).contains(&y) {
+        Some((x as usize, y as usize))
+    } else {
+        None
+    }
+}
+
+pub(crate) fn other(color: Color) -> Color {
+    match color {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+    }
+}
+
+fn slides_along(piece_type: PieceType, diagonal: bool) -> bool {
+    match piece_type {
+        PieceType::Queen => true,
+        PieceType::Rook => !diagonal,
+        PieceType::Bishop => diagonal,
+        _ => false,
+    }
+}
+
+pub(crate) fn piece_on(board: &Board, xy: (usize, usize)) -> Option<Piece> {
+    match board[xy.1][xy.0] {
+        Content::Occupied(p) => Some(p),
+        Content::Empty => None,
+    }
+}
+
+// the first piece from xy in a direction and its square
+fn first_piece(board: &Board, xy: (usize, usize), direction: (i32, i32)) -> Option<((usize, usize), Piece)> {
+    let mut square = xy;
+    while let Some(next) = offset(square, direction.0, direction.1) {
+        square = next;
+        if let Some(p) = piece_on(board, square) {
+            return Some((square, p));
+        }
+    }
+    None
+}
+
+/* squares of the pieces of a colour that attack xy on any board, so the
+exchange evaluation can take pieces off and see what is behind them */
+pub(crate) fn attackers_on(board: &Board, xy: (usize, usize), color: Color) -> Vec<(usize, usize)> {
+    let mut attackers = vec![];
+    let is = |square: (usize, usize), piece_type: PieceType| {
+        piece_on(board, square).is_some_and(|p| p.color == color && p.piece_type == piece_type)
+    };
+
+    // a white pawn attacks upwards, so it stands below the square
+    let pawn_dy = match color { Color::White => 1, Color::Black => -1 };
+    for dx in [-1, 1] {
+        if let Some(square) = offset(xy, dx, pawn_dy).filter(|&square| is(square, PieceType::Pawn)) {
+            attackers.push(square);
+        }
+    }
+    for (dx, dy) in KNIGHT_JUMPS {
+        if let Some(square) = offset(xy, dx, dy).filter(|&square| is(square, PieceType::Knight)) {
+            attackers.push(square);
+        }
+    }
+    for (dx, dy) in KING_STEPS {
+        if let Some(square) = offset(xy, dx, dy).filter(|&square| is(square, PieceType::King)) {
+            attackers.push(square);
+        }
+    }
+    for (directions, diagonal) in [(ROOK_DIRECTIONS, false), (BISHOP_DIRECTIONS, true)] {
+        for direction in directions {
+            if let Some((square, p)) = first_piece(board, xy, direction) {
+                if p.color == color && slides_along(p.piece_type, diagonal) {
+                    attackers.push(square);
+                }
+            }
+        }
+    }
+    attackers
+}
+
+impl Game {
+    fn piece_at(&self, xy: (usize, usize)) -> Option<Piece> {
+        piece_on(&self.board, xy)
+    }
+
+    /* squares of the pieces of a colour that attack xy, whatever stands on it.
+    Works from the square outwards instead of generating every move */
+    pub fn attackers(&self, xy: (usize, usize), color: Color) -> Vec<(usize, usize)> {
+        attackers_on(&self.board, xy, color)
+    }
+
+    pub fn is_attacked_by(&self, xy: (usize, usize), color: Color) -> bool {
+        !self.attackers(xy, color).is_empty()
+    }
+
+    // the pieces guarding the piece on xy, empty for an empty square
+    pub fn defenders(&self, xy: (usize, usize)) -> Vec<(usize, usize)> {
+        match self.piece_at(xy) {
+            Some(p) => self.attackers(xy, p.color),
+            None => vec![],
+        }
+    }
+
+    /* pieces of a colour that can be won: attacked and not defended, or
+    attacked by something worth less. Kings are never hanging */
+    pub fn hanging_pieces(&self, color: Color) -> Vec<HangingPiece> {
+        let mut hanging = vec![];
+        for y in 0..BOARD_SIZE {
+            for x in 0..BOARD_SIZE {
+                let piece = match self.piece_at((x, y)) {
+                    Some(p) if p.color == color && p.piece_type != PieceType::King => p,
+                    _ => continue,
+                };
+                let attackers = self.attackers((x, y), other(color));
+                if attackers.is_empty() {
+                    continue;
+                }
+                let defenders = self.attackers((x, y), color);
+                // the king can not take a defended piece, so it only counts against an undefended one
+                let cheapest = attackers.iter()
+                    .filter_map(|&square| self.piece_at(square))
+                    .filter(|p| p.piece_type != PieceType::King)
+                    .map(|p| piece_value(p.piece_type))
+                    .min();
+                if defenders.is_empty() || cheapest.is_some_and(|value| value < piece_value(piece.piece_type)) {
+                    hanging.push(HangingPiece { square: (x, y), piece: piece, attackers: attackers, defenders: defenders });
+                }
+            }
+        }
+        hanging
+    }
+
+    // pieces of a colour that can not leave the line between an enemy slider and their king
+    pub fn pins(&self, color: Color) -> Vec<Pin> {
+        let king = match color { Color::White => self.w_king, Color::Black => self.b_king };
+        let mut pins = vec![];
+        for (directions, diagonal) in [(ROOK_DIRECTIONS, false), (BISHOP_DIRECTIONS, true)] {
+            for direction in directions {
+                let pinned = match first_piece(&self.board, king, direction) {
+                    Some((square, p)) if p.color == color => square,
+                    _ => continue,
+                };
+                if let Some((pinner, q)) = first_piece(&self.board, pinned, direction) {
+                    if q.color != color && slides_along(q.piece_type, diagonal) {
+                        let mut line = vec![];
+                        let mut square = king;
+                        while square != pinner {
+                            square = offset(square, direction.0, direction.1).expect("the pinner is on the board");
+                            line.push(square);
+                        }
+                        pins.push(Pin { pinned: pinned, pinner: pinner, line: line });
+                    }
+                }
+            }
+        }
+        pins
+    }
+
+    // the pieces giving check to the side to move
+    pub fn checkers(&self) -> Vec<(usize, usize)> {
+        let king = match self.turn { Color::White => self.w_king, Color::Black => self.b_king };
+        self.attackers(king, other(self.turn))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::parse_square;
+
+    fn sq(name: &str) -> (usize, usize) {
+        parse_square(name).unwrap()
+    }
+
+    #[test]
+    fn attackers_and_defenders() {
+        let game = Game::from_fen("4k3/8/3p4/4N3/8/6B1/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(game.attackers(sq("e5"), Color::Black), vec![sq("d6")]);
+        assert_eq!(game.attackers(sq("c5"), Color::Black), vec![sq("d6")]);
+        assert_eq!(game.attackers(sq("c4"), Color::White), vec![sq("e5")]);
+        assert!(game.attackers(sq("d4"), Color::White).is_empty());
+        assert_eq!(game.defenders(sq("e5")), vec![sq("g3")]);
+        assert!(game.defenders(sq("e4")).is_empty());
+        assert!(game.is_attacked_by(sq("a8"), Color::White));
+        assert!(!game.is_attacked_by(sq("e8"), Color::White));
+    }
+
+    #[test]
+    fn hanging_and_pinned() {
+        let game = Game::from_fen("4k3/4r3/8/2n5/8/4B3/8/4K3 w - - 0 1").unwrap();
+        // the knight is not guarded and neither is the pinned bishop
+        let hanging: Vec<(usize, usize)> = game.hanging_pieces(Color::Black).iter().map(|h| h.square).collect();
+        assert_eq!(hanging, vec![sq("c5")]);
+        let hanging = game.hanging_pieces(Color::White);
+        assert_eq!(hanging.len(), 1);
+        assert_eq!(hanging[0].attackers, vec![sq("e7")]);
+
+        let pins = game.pins(Color::White);
+        assert_eq!(pins, vec![Pin { pinned: sq("e3"), pinner: sq("e7"), line: vec![sq("e2"), sq("e3"), sq("e4"), sq("e5"), sq("e6"), sq("e7")] }]);
+        assert!(game.pins(Color::Black).is_empty());
+    }
+
+    #[test]
+    fn defended_piece_next_to_the_king() {
+        // the king attacks the knight, but the rook guards it
+        let game = Game::from_fen("4k3/3N4/8/8/8/8/8/3RK3 w - - 0 1").unwrap();
+        assert!(game.hanging_pieces(Color::White).is_empty());
+        let game = Game::from_fen("4k3/3N4/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(game.hanging_pieces(Color::White).len(), 1);
+    }
+
+    #[test]
+    fn double_check() {
+        let game = Game::from_fen("4k3/8/8/8/1b6/8/4q3/4K1N1 w - - 0 1").unwrap();
+        let mut checkers = game.checkers();
+        checkers.sort();
+        assert_eq!(checkers, vec![sq("b4"), sq("e2")]);
+        assert!(game.is_checked(Color::White));
+        assert!(!game.is_checked(Color::Black));
+    }
+}
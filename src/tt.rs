@@ -0,0 +1,299 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::*;
+use crate::search::{is_mate_score, MATE};
+
+pub const DEFAULT_SIZE_MB: usize = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Bound {
+    Exact,
+    // the score is at least this (fail high)
+    Lower,
+    // the score is at most this (fail low)
+    Upper,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Replacement {
+    Always,
+    DepthPreferred,
+    // entries from earlier searches are replaced first, then by depth
+    AgeAndDepth,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TtEntry {
+    pub depth: u32,
+    pub bound: Bound,
+    pub score: i32,
+    pub best_move: Option<GameMove>,
+}
+
+/* one slot is the key and a packed data word:
+bits 0-15 move, 16-31 score, 32-39 depth, 40-41 bound, 42-49 age,
+bit 50 set so a used slot is never 0.
+The key is stored xor the data, so a slot torn by two threads writing
+at once just fails the key check instead of needing a lock */
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+// shared between search threads, so everything but resizing takes &self
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    replacement: Replacement,
+    age: AtomicU8,
+}
+
+fn square_index(xy: (usize, usize)) -> u64 {
+    (xy.0 + xy.1 * 8) as u64
+}
+
+fn square_from_index(i: u64) -> (usize, usize) {
+    ((i % 8) as usize, (i / 8) as usize)
+}
+
+// 0 is never a real move since from and to always differ
+pub(crate) fn pack_move(mv: Option<GameMove>) -> u64 {
+    match mv {
+        None => 0,
+        Some(mv) => {
+            let promotion = match mv.promotion {
+                None => 0,
+                Some(PieceType::Knight) => 1,
+                Some(PieceType::Bishop) => 2,
+                Some(PieceType::Rook) => 3,
+                Some(_) => 4,
+            };
+            square_index(mv.from) | square_index(mv.to) << 6 | promotion << 12
+        }
+    }
+}
+
+pub(crate) fn unpack_move(bits: u64) -> Option<GameMove> {
+    let bits = bits & 0xffff;
+    if bits == 0 {
+        return None;
+    }
+    let promotion = match (bits >> 12) & 0x7 {
+        1 => Some(PieceType::Knight),
+        2 => Some(PieceType::Bishop),
+        3 => Some(PieceType::Rook),
+        4 => Some(PieceType::Queen),
+        _ => None,
+    };
+    Some(GameMove {
+        from: square_from_index(bits & 0x3f),
+        to: square_from_index((bits >> 6) & 0x3f),
+        promotion: promotion,
+    })
+}
+
+/* mate scores are stored relative to the node instead of the root,
+so the same entry is right when reached at another ply */
+pub fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if is_mate_score(score) {
+        if score > 0 { score + ply as i32 } else { score - ply as i32 }
+    } else {
+        score
+    }
+}
+
+pub fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if is_mate_score(score) {
+        if score > 0 { score - ply as i32 } else { score + ply as i32 }
+    } else {
+        score
+    }
+}
+
+pub(crate) fn pack_data(depth: u32, bound: Bound, score: i32, best_move: Option<GameMove>, age: u8) -> u64 {
+    let bound_bits: u64 = match bound {
+        Bound::Exact => 0,
+        Bound::Lower => 1,
+        Bound::Upper => 2,
+    };
+    let score_bits = score.clamp(-MATE, MATE) as i16 as u16 as u64;
+    pack_move(best_move)
+        | score_bits << 16
+        | (depth.min(255) as u64) << 32
+        | bound_bits << 40
+        | (age as u64) << 42
+        | 1 << 50
+}
+
+pub(crate) fn unpack_data(data: u64) -> (TtEntry, u8) {
+    let bound = match (data >> 40) & 0x3 {
+        1 => Bound::Lower,
+        2 => Bound::Upper,
+        _ => Bound::Exact,
+    };
+    let entry = TtEntry {
+        depth: ((data >> 32) & 0xff) as u32,
+        bound: bound,
+        score: ((data >> 16) & 0xffff) as u16 as i16 as i32,
+        best_move: unpack_move(data),
+    };
+    (entry, ((data >> 42) & 0xff) as u8)
+}
+
+fn new_slots(size_mb: usize) -> Vec<Slot> {
+    (0..slot_count(size_mb)).map(|_| Slot::default()).collect()
+}
+
+pub(crate) fn slot_count(size_mb: usize) -> usize {
+    let wanted = (size_mb.max(1) * 1024 * 1024) / std::mem::size_of::<Slot>();
+    // round down to a power of two so the index is a mask
+    let mut count = 1;
+    while count * 2 <= wanted {
+        count *= 2;
+    }
+    count
+}
+
+pub(crate) fn should_replace(replacement: Replacement, old_data: u64, same_key: bool, depth: u32, age: u8) -> bool {
+    if old_data == 0 || same_key {
+        return true;
+    }
+    let (old, old_age) = unpack_data(old_data);
+    match replacement {
+        Replacement::Always => true,
+        Replacement::DepthPreferred => depth >= old.depth,
+        Replacement::AgeAndDepth => old_age != age || depth >= old.depth,
+    }
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        TranspositionTable::with_replacement(size_mb, Replacement::AgeAndDepth)
+    }
+
+    pub fn with_replacement(size_mb: usize, replacement: Replacement) -> TranspositionTable {
+        TranspositionTable {
+            slots: new_slots(size_mb),
+            replacement: replacement,
+            age: AtomicU8::new(0),
+        }
+    }
+
+    pub fn resize(&mut self, size_mb: usize) {
+        self.slots = new_slots(size_mb);
+    }
+
+    pub fn clear(&self) {
+        for slot in self.slots.iter() {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    // call once per search so old entries are replaced first
+    pub fn new_search(&self) {
+        self.age.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key as usize) & (self.slots.len() - 1)
+    }
+
+    // (stored key, data), the stored key is already un-xored
+    fn read(&self, index: usize) -> (u64, u64) {
+        let slot = &self.slots[index];
+        let data = slot.data.load(Ordering::Relaxed);
+        (slot.key.load(Ordering::Relaxed) ^ data, data)
+    }
+
+    pub fn probe(&self, key: u64, ply: usize) -> Option<TtEntry> {
+        let (slot_key, data) = self.read(self.index(key));
+        if data == 0 || slot_key != key {
+            return None;
+        }
+        let (mut entry, _) = unpack_data(data);
+        entry.score = score_from_tt(entry.score, ply);
+        Some(entry)
+    }
+
+    pub fn store(&self, key: u64, depth: u32, bound: Bound, score: i32, best_move: Option<GameMove>, ply: usize) {
+        let index = self.index(key);
+        let age = self.age.load(Ordering::Relaxed);
+        let (slot_key, old_data) = self.read(index);
+        if !should_replace(self.replacement, old_data, slot_key == key, depth, age) {
+            return;
+        }
+        // keep the old move if this search did not find one
+        let best_move = match best_move {
+            None if slot_key == key => unpack_move(old_data),
+            _ => best_move,
+        };
+        let data = pack_data(depth, bound, score_to_tt(score, ply), best_move, age);
+        self.slots[index].key.store(key ^ data, Ordering::Relaxed);
+        self.slots[index].data.store(data, Ordering::Relaxed);
+    }
+
+    // permille of a sample of slots used by the current search, as UCI wants it
+    pub fn hashfull(&self) -> usize {
+        let age = self.age.load(Ordering::Relaxed);
+        let sample = self.slots.len().min(1000);
+        let used = (0..sample)
+            .map(|i| self.read(i).1)
+            .filter(|&data| data != 0 && unpack_data(data).1 == age)
+            .count();
+        used * 1000 / sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        let mv = GameMove { from: (6, 1), to: (6, 0), promotion: Some(PieceType::Knight) };
+        tt.store(12345, 7, Bound::Lower, -250, Some(mv), 3);
+        assert_eq!(tt.probe(12345, 3), Some(TtEntry {
+            depth: 7,
+            bound: Bound::Lower,
+            score: -250,
+            best_move: Some(mv)
+        }));
+        assert_eq!(tt.probe(12345 + tt.len() as u64, 3), None);
+    }
+
+    #[test]
+    fn mate_scores_follow_ply() {
+        let tt = TranspositionTable::new(1);
+        // mate in 5 plies from the root, found at ply 2
+        tt.store(99, 3, Bound::Exact, MATE - 5, None, 2);
+        assert_eq!(tt.probe(99, 2).unwrap().score, MATE - 5);
+        assert_eq!(tt.probe(99, 4).unwrap().score, MATE - 7);
+    }
+
+    #[test]
+    fn depth_preferred_keeps_deeper_entry() {
+        let tt = TranspositionTable::with_replacement(1, Replacement::DepthPreferred);
+        let other = 7 + tt.len() as u64;
+        tt.store(7, 6, Bound::Exact, 10, None, 0);
+        tt.store(other, 2, Bound::Exact, 20, None, 0);
+        assert_eq!(tt.probe(7, 0).unwrap().score, 10);
+
+        let tt = TranspositionTable::with_replacement(1, Replacement::AgeAndDepth);
+        tt.store(7, 6, Bound::Exact, 10, None, 0);
+        tt.new_search();
+        tt.store(other, 2, Bound::Exact, 20, None, 0);
+        assert_eq!(tt.probe(7, 0), None);
+        assert_eq!(tt.probe(other, 0).unwrap().score, 20);
+    }
+}
@@ -0,0 +1,294 @@
+use crate::*;
+use crate::events::{GameEvent, GameOverReason};
+
+// the keys the terminal frontend reacts to, whatever library reads them
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TuiKey {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Escape,
+    Backspace,
+    Char(char),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TuiAction {
+    None,
+    // a move picked by the player, not made yet so a network game can ask the server first
+    Move(GameMove),
+    Quit,
+}
+
+// how a square is drawn, the frontend picks the colours
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SquareMark {
+    Plain,
+    Cursor,
+    Selected,
+    Destination,
+    LastMove,
+}
+
+/* everything the terminal UI shows apart from the drawing itself: a cursor
+for picking moves, typed SAN moves, the move list and a status line */
+pub struct TuiState {
+    game: Game,
+    sans: Vec<String>,
+    // the move number and side of the first move, for the move list
+    first_move: (u32, Color),
+    cursor: (usize, usize),
+    selected: Option<(usize, usize)>,
+    destinations: Vec<(usize, usize)>,
+    input: String,
+    status: String,
+    last_move: Option<GameMove>,
+    // the colour played from this terminal, None plays both
+    player: Option<Color>,
+}
+
+impl TuiState {
+    pub fn new(game: Game, player: Option<Color>) -> TuiState {
+        let mut game = game;
+        game.set_recording(true);
+        let first_move = (game.fullmove_number, game.get_turn());
+        let mut state = TuiState {
+            game: game,
+            sans: vec![],
+            first_move: first_move,
+            cursor: (4, 6),
+            selected: None,
+            destinations: vec![],
+            input: String::new(),
+            status: String::new(),
+            last_move: None,
+            player: player,
+        };
+        state.status = state.turn_status();
+        state
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    pub fn set_status(&mut self, status: &str) {
+        self.status = status.to_string();
+    }
+
+    pub fn cursor(&self) -> (usize, usize) {
+        self.cursor
+    }
+
+    // black's board is shown from black's side
+    pub fn is_flipped(&self) -> bool {
+        self.player == Some(Color::Black)
+    }
+
+    pub fn is_my_turn(&self) -> bool {
+        self.player.is_none_or(|color| color == self.game.get_turn())
+    }
+
+    pub fn mark(&self, xy: (usize, usize)) -> SquareMark {
+        if xy == self.cursor {
+            SquareMark::Cursor
+        } else if Some(xy) == self.selected {
+            SquareMark::Selected
+        } else if self.destinations.contains(&xy) {
+            SquareMark::Destination
+        } else if self.last_move.is_some_and(|mv| mv.from == xy || mv.to == xy) {
+            SquareMark::LastMove
+        } else {
+            SquareMark::Plain
+        }
+    }
+
+    // "1. e4 e5" and so on, one line per move number
+    pub fn move_list(&self) -> Vec<String> {
+        let (first_number, first_color) = self.first_move;
+        let mut sans: Vec<&str> = self.sans.iter().map(|san| san.as_str()).collect();
+        if first_color == Color::Black {
+            sans.insert(0, "...");
+        }
+        sans.chunks(2)
+            .enumerate()
+            .map(|(i, pair)| format!("{}. {}", first_number as usize + i, pair.join(" ")))
+            .collect()
+    }
+
+    fn turn_status(&self) -> String {
+        let turn = match self.game.get_turn() { Color::White => "White", Color::Black => "Black" };
+        if self.player.is_some() && !self.is_my_turn() {
+            format!("{} to move, waiting for the opponent", turn)
+        } else {
+            format!("{} to move", turn)
+        }
+    }
+
+    fn move_cursor(&mut self, dx: i32, dy: i32) {
+        // the arrows follow the screen, so they turn around with the board
+        let (dx, dy) = if self.is_flipped() { (-dx, -dy) } else { (dx, dy) };
+        let x = (self.cursor.0 as i32 + dx).clamp(0, BOARD_SIZE as i32 - 1);
+        let y = (self.cursor.1 as i32 + dy).clamp(0, BOARD_SIZE as i32 - 1);
+        self.cursor = (x as usize, y as usize);
+    }
+
+    fn deselect(&mut self) {
+        self.selected = None;
+        self.destinations.clear();
+    }
+
+    // first Enter picks a piece, the second one a highlighted square
+    fn select(&mut self) -> TuiAction {
+        if let Some(from) = self.selected {
+            if self.destinations.contains(&self.cursor) {
+                let to = self.cursor;
+                self.deselect();
+                // typing the move gives other pieces, the cursor always makes a queen
+                let promotion = if self.game.is_promotion_move(from, to) { Some(PieceType::Queen) } else { None };
+                return TuiAction::Move(GameMove { from: from, to: to, promotion: promotion });
+            }
+            self.deselect();
+            if from == self.cursor {
+                return TuiAction::None;
+            }
+        }
+        let legal = self.game.legal_moves();
+        self.destinations = match self.game.get_destinations(self.cursor) {
+            Destinations::Exists(squares) => squares.into_iter()
+                .filter(|&to| legal.iter().any(|mv| mv.from == self.cursor && mv.to == to))
+                .collect(),
+            Destinations::None => vec![],
+        };
+        if !self.destinations.is_empty() {
+            self.selected = Some(self.cursor);
+        }
+        TuiAction::None
+    }
+
+    pub fn handle_key(&mut self, key: TuiKey) -> TuiAction {
+        match key {
+            TuiKey::Up => self.move_cursor(0, -1),
+            TuiKey::Down => self.move_cursor(0, 1),
+            TuiKey::Left => self.move_cursor(-1, 0),
+            TuiKey::Right => self.move_cursor(1, 0),
+            TuiKey::Char(c) if !c.is_control() => self.input.push(c),
+            TuiKey::Char(_) => (),
+            TuiKey::Backspace => {
+                self.input.pop();
+            },
+            TuiKey::Escape => {
+                if self.input.is_empty() && self.selected.is_none() {
+                    return TuiAction::Quit;
+                }
+                self.input.clear();
+                self.deselect();
+            },
+            TuiKey::Enter => {
+                if !self.is_my_turn() {
+                    self.status = String::from("Not your turn");
+                    return TuiAction::None;
+                }
+                if self.input.is_empty() {
+                    return self.select();
+                }
+                let text = std::mem::take(&mut self.input);
+                match self.game.parse_san(text.trim()).or_else(|| self.game.parse_uci_move(text.trim())) {
+                    Some(mv) => return TuiAction::Move(mv),
+                    None => self.status = format!("{} is not a legal move", text.trim()),
+                }
+            },
+        }
+        TuiAction::None
+    }
+
+    // makes a move from either side, picked here or received over the network
+    pub fn apply_move(&mut self, mv: &GameMove) -> Result<(), MoveError> {
+        let san = self.game.to_san(mv);
+        self.game.make_move(mv)?;
+        self.sans.push(san);
+        self.last_move = Some(*mv);
+        self.deselect();
+        self.status = self.turn_status();
+        for event in self.game.drain_events() {
+            match event {
+                GameEvent::Check { .. } => self.status += ", check",
+                GameEvent::GameOver(GameOverReason::Checkmate { winner }) => self.status = format!("Checkmate, {:?} wins", winner),
+                GameEvent::GameOver(reason) => self.status = format!("Draw by {:?}", reason),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_and_typed_moves() {
+        let mut state = TuiState::new(create_game(), None);
+        // e2 is under the cursor at the start
+        assert_eq!(state.handle_key(TuiKey::Enter), TuiAction::None);
+        assert_eq!(state.mark((4, 4)), SquareMark::Destination);
+        state.handle_key(TuiKey::Up);
+        state.handle_key(TuiKey::Up);
+        let mv = match state.handle_key(TuiKey::Enter) {
+            TuiAction::Move(mv) => mv,
+            other => panic!("expected a move, got {:?}", other),
+        };
+        state.apply_move(&mv).unwrap();
+        assert_eq!(state.mark((4, 6)), SquareMark::LastMove);
+
+        for c in "Nf6".chars() {
+            state.handle_key(TuiKey::Char(c));
+        }
+        let mv = state.handle_key(TuiKey::Enter);
+        assert_eq!(mv, TuiAction::Move(state.game().parse_uci_move("g8f6").unwrap()));
+        if let TuiAction::Move(mv) = mv {
+            state.apply_move(&mv).unwrap();
+        }
+        for c in "Ke3".chars() {
+            state.handle_key(TuiKey::Char(c));
+        }
+        assert_eq!(state.handle_key(TuiKey::Enter), TuiAction::None);
+        assert_eq!(state.status(), "Ke3 is not a legal move");
+        assert_eq!(state.move_list(), vec!["1. e4 Nf6"]);
+        assert_eq!(state.handle_key(TuiKey::Escape), TuiAction::Quit);
+    }
+
+    #[test]
+    fn network_player_waits_for_their_turn() {
+        let mut state = TuiState::new(create_game(), Some(Color::Black));
+        assert!(state.is_flipped());
+        assert_eq!(state.handle_key(TuiKey::Enter), TuiAction::None);
+        assert_eq!(state.status(), "Not your turn");
+        // from black's side up on the screen is down the board
+        state.handle_key(TuiKey::Up);
+        assert_eq!(state.cursor(), (4, 7));
+
+        state.apply_move(&state.game().parse_uci_move("e2e4").unwrap()).unwrap();
+        assert!(state.is_my_turn());
+        assert_eq!(state.status(), "Black to move");
+    }
+
+    #[test]
+    fn move_list_from_a_black_start() {
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 30").unwrap();
+        let mut state = TuiState::new(game, None);
+        state.apply_move(&state.game().parse_uci_move("e8d8").unwrap()).unwrap();
+        state.apply_move(&state.game().parse_uci_move("e1d1").unwrap()).unwrap();
+        assert_eq!(state.move_list(), vec!["30. ... Kd8", "31. Kd1"]);
+    }
+}
@@ -0,0 +1,395 @@
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::*;
+use crate::search::{is_mate_score, SearchLimits, SearchResult, Searcher, MATE};
+use crate::tt::{TranspositionTable, DEFAULT_SIZE_MB};
+use crate::polyglot::{Book, BookRng};
+use crate::bitbase::Bitbases;
+
+pub const ENGINE_NAME: &str = "antmag-chess";
+pub const ENGINE_AUTHOR: &str = "INDA22PlusPlus";
+
+const MAX_HASH_MB: usize = 1024;
+const MAX_THREADS: usize = 64;
+
+pub fn send<W: Write>(out: &Mutex<W>, line: &str) {
+    let mut out = out.lock().expect("output lock poisoned");
+    // nothing sensible to do if the GUI went away
+    writeln!(out, "{}", line).ok();
+    out.flush().ok();
+}
+
+// "cp 35" or "mate 3", mate is counted in moves, negative when getting mated
+pub fn format_score(score: i32) -> String {
+    if is_mate_score(score) {
+        if score > 0 {
+            format!("mate {}", (MATE - score + 1) / 2)
+        } else {
+            format!("mate {}", -(MATE + score) / 2)
+        }
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+pub fn format_info(result: &SearchResult, hashfull: usize) -> String {
+    let millis = result.time.as_millis() as u64;
+    let nps = result.nodes * 1000 / millis.max(1);
+    let pv: Vec<String> = result.pv.iter().map(|mv| mv.to_uci()).collect();
+    format!("info depth {} score {} nodes {} nps {} time {} hashfull {} pv {}",
+        result.depth, format_score(result.score), result.nodes, nps, millis, hashfull, pv.join(" "))
+}
+
+pub fn format_bestmove(result: &SearchResult) -> String {
+    match result.best_move {
+        None => String::from("bestmove 0000"),
+        Some(mv) => match result.pv.get(1) {
+            Some(ponder) if result.pv[0] == mv => format!("bestmove {} ponder {}", mv.to_uci(), ponder.to_uci()),
+            _ => format!("bestmove {}", mv.to_uci()),
+        }
+    }
+}
+
+// "startpos moves e2e4 e7e5" or "fen <fen> moves ...", everything after the position keyword
+pub fn parse_position(args: &[&str]) -> Option<Game> {
+    let moves_at = args.iter().position(|&a| a == "moves").unwrap_or(args.len());
+    let mut game = match args.first() {
+        Some(&"startpos") => create_game(),
+        Some(&"fen") => Game::from_fen(&args[1..moves_at].join(" ")).ok()?,
+        _ => return None,
+    };
+    for text in args.iter().skip(moves_at + 1) {
+        let mv = game.parse_uci_move(text)?;
+        game.make_move(&mv).ok()?;
+    }
+    Some(game)
+}
+
+// time for this move out of what is left on the clock
+pub fn allocate_time(time_left: Duration, increment: Duration, moves_to_go: Option<u32>) -> Duration {
+    let moves = moves_to_go.unwrap_or(30).max(1);
+    let budget = time_left / moves + increment * 3 / 4;
+    budget.min(time_left / 2).max(Duration::from_millis(1))
+}
+
+fn parse_go(args: &[&str], turn: Color) -> SearchLimits {
+    let mut limits = SearchLimits::default();
+    let mut clock: [Option<Duration>; 2] = [None, None];
+    let mut increment = [Duration::ZERO, Duration::ZERO];
+    let mut moves_to_go = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).and_then(|v| v.parse::<u64>().ok());
+        match (args[i], value) {
+            ("depth", Some(v)) => limits.depth = Some(v as u32),
+            ("nodes", Some(v)) => limits.nodes = Some(v),
+            ("movetime", Some(v)) => limits.movetime = Some(Duration::from_millis(v)),
+            ("wtime", Some(v)) => clock[0] = Some(Duration::from_millis(v)),
+            ("btime", Some(v)) => clock[1] = Some(Duration::from_millis(v)),
+            ("winc", Some(v)) => increment[0] = Duration::from_millis(v),
+            ("binc", Some(v)) => increment[1] = Duration::from_millis(v),
+            ("movestogo", Some(v)) => moves_to_go = Some(v as u32),
+            _ => {
+                // flags without a value, like infinite
+                i += 1;
+                continue;
+            }
+        }
+        i += 2;
+    }
+
+    let side = match turn { Color::White => 0, Color::Black => 1 };
+    if limits.movetime.is_none() {
+        if let Some(time_left) = clock[side] {
+            limits.movetime = Some(allocate_time(time_left, increment[side], moves_to_go));
+        }
+    }
+    limits
+}
+
+pub struct Uci<W: Write + Send + 'static> {
+    out: Arc<Mutex<W>>,
+    game: Game,
+    // owned by the worker thread while a search is running
+    searcher: Option<Searcher>,
+    worker: Option<JoinHandle<Searcher>>,
+    stop: Arc<AtomicBool>,
+    // go infinite, bestmove waits for stop even when the search is over. Stop clears it,
+    // the search clears its own stop flag when it returns
+    infinite: Arc<AtomicBool>,
+    book: Option<Book>,
+    rng: BookRng,
+}
+
+impl<W: Write + Send + 'static> Uci<W> {
+    pub fn new(out: Arc<Mutex<W>>) -> Uci<W> {
+        let searcher = Searcher::new(SearchLimits::default());
+        Uci {
+            out: out,
+            game: create_game(),
+            stop: searcher.stop_handle(),
+            searcher: Some(searcher),
+            worker: None,
+            infinite: Arc::new(AtomicBool::new(false)),
+            book: None,
+            rng: BookRng::from_time(),
+        }
+    }
+
+    fn send(&self, line: &str) {
+        send(&self.out, line);
+    }
+
+    // blocks until a running search has printed its bestmove
+    pub fn wait(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            self.searcher = Some(worker.join().expect("search thread panicked"));
+        }
+    }
+
+    pub fn stop(&mut self) {
+        if self.worker.is_some() {
+            self.infinite.store(false, Ordering::Relaxed);
+            self.stop.store(true, Ordering::Relaxed);
+            self.wait();
+        }
+    }
+
+    fn searcher(&mut self) -> &mut Searcher {
+        self.wait();
+        self.searcher.as_mut().expect("searcher missing after wait")
+    }
+
+    // returns false once the engine should quit
+    pub fn handle_line(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match tokens.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return true,
+        };
+        match command {
+            "uci" => {
+                self.send(&format!("id name {}", ENGINE_NAME));
+                self.send(&format!("id author {}", ENGINE_AUTHOR));
+                self.send(&format!("option name Hash type spin default {} min 1 max {}", DEFAULT_SIZE_MB, MAX_HASH_MB));
+                self.send(&format!("option name Threads type spin default 1 min 1 max {}", MAX_THREADS));
+                self.send("option name Clear Hash type button");
+                self.send("option name BookFile type string default <empty>");
+                self.send("option name BitbasePath type string default <empty>");
+                self.send("uciok");
+            },
+            "isready" => self.send("readyok"),
+            "ucinewgame" => {
+                self.stop();
+                self.searcher().table().clear();
+                self.game = create_game();
+            },
+            "position" => {
+                self.stop();
+                match parse_position(args) {
+                    Some(game) => self.game = game,
+                    None => self.send(&format!("info string invalid position: {}", args.join(" "))),
+                }
+            },
+            "setoption" => {
+                self.stop();
+                self.set_option(args);
+            },
+            "go" => {
+                self.stop();
+                let limits = parse_go(args, self.game.get_turn());
+                self.infinite.store(args.contains(&"infinite"), Ordering::Relaxed);
+                self.go(limits);
+            },
+            "stop" => self.stop(),
+            "quit" => {
+                self.stop();
+                return false;
+            },
+            // debug, register, ponderhit and unknown commands
+            _ => (),
+        }
+        true
+    }
+
+    fn set_option(&mut self, args: &[&str]) {
+        let value_at = args.iter().position(|&a| a == "value").unwrap_or(args.len());
+        if args.first() != Some(&"name") {
+            return;
+        }
+        let name = args[1..value_at].join(" ");
+        let text = args.get(value_at + 1..).map(|v| v.join(" ")).unwrap_or_default();
+        if name.to_lowercase() == "bookfile" {
+            self.book = match text.as_str() {
+                "" | "<empty>" => None,
+                path => match Book::open(path) {
+                    Ok(book) => Some(book),
+                    Err(e) => {
+                        self.send(&format!("info string could not open book {}: {:?}", path, e));
+                        None
+                    }
+                },
+            };
+            return;
+        }
+        if name.to_lowercase() == "bitbasepath" {
+            let bitbases = match text.as_str() {
+                "" | "<empty>" => None,
+                dir => match Bitbases::load_dir(dir) {
+                    Ok(bitbases) => Some(Arc::new(bitbases)),
+                    Err(e) => {
+                        self.send(&format!("info string could not load bitbases from {}: {:?}", dir, e));
+                        None
+                    }
+                },
+            };
+            self.searcher().set_bitbases(bitbases);
+            return;
+        }
+        let value = text.parse::<usize>().ok();
+        match (name.to_lowercase().as_str(), value) {
+            ("hash", Some(mb)) => self.searcher().set_table(TranspositionTable::new(mb.clamp(1, MAX_HASH_MB))),
+            ("threads", Some(n)) => self.searcher().set_threads(n.clamp(1, MAX_THREADS)),
+            ("clear hash", _) => self.searcher().table().clear(),
+            _ => self.send(&format!("info string unknown option {}", name)),
+        }
+    }
+
+    fn go(&mut self, limits: SearchLimits) {
+        // book moves are played at once, without searching
+        let book_move = if self.infinite.load(Ordering::Relaxed) { None } else { self.book.as_ref().and_then(|book| book.pick(&self.game, &mut self.rng)) };
+        if let Some(mv) = book_move {
+            self.send(&format!("bestmove {}", mv.to_uci()));
+            return;
+        }
+        let mut searcher = self.searcher.take().expect("searcher missing before go");
+        searcher.set_limits(limits);
+
+        let out = self.out.clone();
+        let table = searcher.table().clone();
+        searcher.set_info_callback(Some(Box::new(move |result: &SearchResult| {
+            send(&out, &format_info(result, table.hashfull()));
+        })));
+
+        self.stop.store(false, Ordering::Relaxed);
+        let game = self.game.clone();
+        let out = self.out.clone();
+        let infinite = self.infinite.clone();
+        self.worker = Some(thread::spawn(move || {
+            let result = searcher.run(&game);
+            // a mate or the deepest depth ends the search early, UCI still wants bestmove after stop
+            while infinite.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(5));
+            }
+            send(&out, &format_bestmove(&result));
+            searcher
+        }));
+    }
+}
+
+pub fn run<R: BufRead, W: Write + Send + 'static>(input: R, output: W) {
+    let mut uci = Uci::new(Arc::new(Mutex::new(output)));
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if !uci.handle_line(&line) {
+            return;
+        }
+    }
+    // end of input, let a running search finish, an infinite one would never end
+    if uci.infinite.load(Ordering::Relaxed) {
+        uci.stop();
+    } else {
+        uci.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(lines: &[&str]) -> String {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let mut uci = Uci::new(out.clone());
+        for line in lines {
+            uci.handle_line(line);
+        }
+        uci.wait();
+        let bytes = out.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn handshake() {
+        let output = session(&["uci", "setoption name Hash value 4", "isready"]);
+        assert!(output.contains("id name antmag-chess"));
+        assert!(output.contains("option name Threads type spin"));
+        assert!(output.ends_with("uciok\nreadyok\n"));
+    }
+
+    #[test]
+    fn go_after_moves() {
+        let output = session(&["position startpos moves e2e4 e7e5", "go depth 2"]);
+        assert!(output.contains("info depth 1 score cp"));
+        assert!(output.contains("info depth 2 "));
+        assert!(output.lines().last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn finds_mate_from_fen() {
+        let output = session(&["position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "go depth 3"]);
+        assert!(output.contains("score mate 1"));
+        assert_eq!(output.lines().last().unwrap(), "bestmove a1a8");
+    }
+
+    #[test]
+    fn stop_infinite_search() {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let mut uci = Uci::new(out.clone());
+        uci.handle_line("position startpos");
+        uci.handle_line("go infinite");
+        thread::sleep(Duration::from_millis(50));
+        uci.handle_line("stop");
+        let output = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert!(output.lines().last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn infinite_mate_waits_for_stop() {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let mut uci = Uci::new(out.clone());
+        uci.handle_line("position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        uci.handle_line("go infinite");
+        thread::sleep(Duration::from_millis(200));
+        assert!(!String::from_utf8(out.lock().unwrap().clone()).unwrap().contains("bestmove"));
+        uci.handle_line("stop");
+        let output = String::from_utf8(out.lock().unwrap().clone()).unwrap();
+        assert_eq!(output.lines().last().unwrap(), "bestmove a1a8");
+    }
+
+    #[test]
+    fn plays_from_book() {
+        // one file per test process, so parallel runs do not share it
+        let path = std::env::temp_dir().join(format!("antmag_uci_book_{}.bin", std::process::id()));
+        let mut builder = crate::polyglot::BookBuilder::new(2);
+        builder.add_pgn("1. g4 e5 1-0").unwrap();
+        builder.build().save(&path).unwrap();
+        let output = session(&[&format!("setoption name BookFile value {}", path.display()), "position startpos", "go depth 5"]);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(output, "bestmove g2g4\n");
+    }
+
+    #[test]
+    fn clock_allocation() {
+        let limits = parse_go(&["wtime", "60000", "btime", "1000", "binc", "100"], Color::Black);
+        assert_eq!(limits.movetime, Some(Duration::from_millis(1000) / 30 + Duration::from_millis(75)));
+        assert_eq!(parse_go(&["depth", "5"], Color::White).depth, Some(5));
+    }
+}
@@ -0,0 +1,396 @@
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::*;
+use crate::search::{is_mate_score, SearchLimits, SearchResult, Searcher, MATE};
+use crate::uci::{allocate_time, send, ENGINE_NAME};
+
+// xboard wants mate scores as 100000 + moves to mate
+const XBOARD_MATE: i32 = 100000;
+
+pub fn format_thinking(result: &SearchResult) -> String {
+    let score = if is_mate_score(result.score) {
+        if result.score > 0 {
+            XBOARD_MATE + (MATE - result.score + 1) / 2
+        } else {
+            -XBOARD_MATE - (MATE + result.score) / 2
+        }
+    } else {
+        result.score
+    };
+    let pv: Vec<String> = result.pv.iter().map(|mv| mv.to_uci()).collect();
+    // time is in centiseconds
+    format!("{} {} {} {} {}", result.depth, score, result.time.as_millis() / 10, result.nodes, pv.join(" "))
+}
+
+// the result line once the game is over, e.g. "1-0 {White mates}"
+pub fn game_result(game: &Game) -> Option<&'static str> {
+    if game.legal_moves().is_empty() {
+        return Some(match (game.is_checked(game.turn), game.turn) {
+            (true, Color::White) => "0-1 {Black mates}",
+            (true, Color::Black) => "1-0 {White mates}",
+            (false, _) => "1/2-1/2 {Stalemate}",
+        });
+    }
+    if game.halfmove_clock >= 100 {
+        return Some("1/2-1/2 {Fifty move rule}");
+    }
+    if game.repetition_count() >= 2 {
+        return Some("1/2-1/2 {Draw by repetition}");
+    }
+    None
+}
+
+// "level 40 5 0" is 40 moves in 5 minutes, the base can also be "0:30"
+fn parse_level(args: &[&str]) -> Option<(u32, Duration, Duration)> {
+    if args.len() != 3 {
+        return None;
+    }
+    let moves = args[0].parse().ok()?;
+    let base = match args[1].split_once(':') {
+        Some((min, sec)) => Duration::from_secs(min.parse::<u64>().ok()? * 60 + sec.parse::<u64>().ok()?),
+        None => Duration::from_secs(args[1].parse::<u64>().ok()? * 60),
+    };
+    let increment = Duration::from_secs_f64(args[2].parse::<f64>().ok()?.max(0.0));
+    Some((moves, base, increment))
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct TimeControl {
+    // moves per session, 0 for the whole game
+    moves_per_session: u32,
+    increment: Duration,
+    // from the time command, what is left on the engine's clock
+    time_left: Option<Duration>,
+    // st and sd
+    fixed_time: Option<Duration>,
+    max_depth: Option<u32>,
+}
+
+impl TimeControl {
+    fn limits(&self, game: &Game) -> SearchLimits {
+        let movetime = match (self.fixed_time, self.time_left) {
+            (Some(fixed), _) => Some(fixed),
+            (None, Some(time_left)) => {
+                let moves_to_go = match self.moves_per_session {
+                    0 => None,
+                    n => Some(n - (game.fullmove_number - 1) % n),
+                };
+                Some(allocate_time(time_left, self.increment, moves_to_go))
+            },
+            (None, None) => None,
+        };
+        let mut limits = SearchLimits { depth: self.max_depth, movetime: movetime, ..Default::default() };
+        if limits.depth.is_none() && limits.movetime.is_none() {
+            // no time control given yet
+            limits.movetime = Some(Duration::from_secs(5));
+        }
+        limits
+    }
+}
+
+pub struct Xboard<W: Write + Send + 'static> {
+    out: Arc<Mutex<W>>,
+    game: Game,
+    // the side the engine plays, None in force mode
+    engine_color: Option<Color>,
+    time_control: TimeControl,
+    // owned by the worker thread while the engine is thinking
+    searcher: Option<Searcher>,
+    // hands back the game after the engine's move, None when the move was thrown away
+    worker: Option<JoinHandle<(Searcher, Option<Game>)>>,
+    stop: Arc<AtomicBool>,
+    // set under the output lock, so a cancelled search never sends its move
+    discard: Arc<AtomicBool>,
+    post: Arc<AtomicBool>,
+}
+
+impl<W: Write + Send + 'static> Xboard<W> {
+    pub fn new(out: Arc<Mutex<W>>) -> Xboard<W> {
+        let searcher = Searcher::new(SearchLimits::default());
+        Xboard {
+            out: out,
+            game: create_game(),
+            engine_color: Some(Color::Black),
+            time_control: TimeControl::default(),
+            stop: searcher.stop_handle(),
+            searcher: Some(searcher),
+            worker: None,
+            discard: Arc::new(AtomicBool::new(false)),
+            post: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn send(&self, line: &str) {
+        send(&self.out, line);
+    }
+
+    /* blocks until the engine has sent its move and takes over the game
+    after it, so every command sees the position the GUI sees */
+    pub fn wait(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            let (searcher, game) = worker.join().expect("search thread panicked");
+            self.searcher = Some(searcher);
+            if let Some(game) = game {
+                self.game = game;
+                // the worker already claimed the result
+                if game_result(&self.game).is_some() {
+                    self.engine_color = None;
+                }
+            }
+        }
+    }
+
+    // move now
+    pub fn stop(&mut self) {
+        if self.worker.is_some() {
+            self.stop.store(true, Ordering::Relaxed);
+            self.wait();
+        }
+    }
+
+    // stops the search without playing or sending its move
+    pub fn cancel(&mut self) {
+        if self.worker.is_some() {
+            {
+                let _out = self.out.lock().expect("output lock poisoned");
+                self.discard.store(true, Ordering::Relaxed);
+            }
+            self.stop();
+        }
+    }
+
+    fn check_result(&mut self) {
+        if let Some(result) = game_result(&self.game) {
+            self.send(result);
+            self.engine_color = None;
+        }
+    }
+
+    // returns false once the engine should quit
+    pub fn handle_line(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match tokens.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return true,
+        };
+        // ? makes the engine move now, the commands that leave the game throw the search away
+        match command {
+            "?" => {
+                self.stop();
+                return true;
+            },
+            "new" | "force" | "result" | "quit" => self.cancel(),
+            _ => self.wait(),
+        }
+        match command {
+            "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer" | "otim" | "name" | "rating" => (),
+            "protover" => {
+                self.send(&format!(
+                    "feature myname=\"{}\" ping=1 setboard=1 usermove=1 san=0 colors=0 sigint=0 sigterm=0 analyze=0 done=1",
+                    ENGINE_NAME));
+            },
+            "new" => {
+                self.game = create_game();
+                self.engine_color = Some(Color::Black);
+                self.time_control.fixed_time = None;
+                self.time_control.max_depth = None;
+                self.searcher.as_mut().expect("searcher missing after wait").table().clear();
+            },
+            "force" | "result" => self.engine_color = None,
+            "go" => {
+                self.engine_color = Some(self.game.get_turn());
+                self.think();
+            },
+            "playother" => self.engine_color = Some(match self.game.get_turn() {
+                Color::White => Color::Black,
+                Color::Black => Color::White,
+            }),
+            "setboard" => match Game::from_fen(&args.join(" ")) {
+                Ok(game) => self.game = game,
+                Err(e) => self.send(&format!("tellusererror Illegal position: {:?}", e)),
+            },
+            "usermove" => self.user_move(args.first().copied().unwrap_or("")),
+            "level" => match parse_level(args) {
+                Some((moves, base, increment)) => {
+                    self.time_control.moves_per_session = moves;
+                    self.time_control.increment = increment;
+                    self.time_control.time_left = Some(base);
+                    self.time_control.fixed_time = None;
+                },
+                None => self.send(&format!("Error (bad level): {}", args.join(" "))),
+            },
+            "st" => match args.first().and_then(|v| v.parse::<u64>().ok()) {
+                Some(seconds) => self.time_control.fixed_time = Some(Duration::from_secs(seconds)),
+                None => self.send("Error (bad st): st"),
+            },
+            "sd" => match args.first().and_then(|v| v.parse::<u32>().ok()) {
+                Some(depth) => self.time_control.max_depth = Some(depth),
+                None => self.send("Error (bad sd): sd"),
+            },
+            "time" => {
+                if let Some(centis) = args.first().and_then(|v| v.parse::<u64>().ok()) {
+                    self.time_control.time_left = Some(Duration::from_millis(centis * 10));
+                }
+            },
+            "ping" => self.send(&format!("pong {}", args.first().unwrap_or(&""))),
+            "post" => self.post.store(true, Ordering::Relaxed),
+            "nopost" => self.post.store(false, Ordering::Relaxed),
+            "quit" => return false,
+            _ => {
+                // protover 2 promises usermove, but older GUIs send bare moves
+                if self.game.parse_uci_move(command).is_some() {
+                    self.user_move(command);
+                } else {
+                    self.send(&format!("Error (unknown command): {}", command));
+                }
+            }
+        }
+        true
+    }
+
+    fn user_move(&mut self, text: &str) {
+        let mv = match self.game.parse_uci_move(text) {
+            Some(mv) => mv,
+            None => {
+                self.send(&format!("Illegal move: {}", text));
+                return;
+            }
+        };
+        self.game.make_move(&mv).expect("parse_uci_move only returns legal moves");
+        self.check_result();
+        if self.engine_color == Some(self.game.get_turn()) {
+            self.think();
+        }
+    }
+
+    fn think(&mut self) {
+        if game_result(&self.game).is_some() {
+            return;
+        }
+        let mut searcher = self.searcher.take().expect("searcher missing before go");
+        searcher.set_limits(self.time_control.limits(&self.game));
+
+        let out = self.out.clone();
+        let post = self.post.clone();
+        searcher.set_info_callback(Some(Box::new(move |result: &SearchResult| {
+            if post.load(Ordering::Relaxed) {
+                send(&out, &format_thinking(result));
+            }
+        })));
+
+        self.stop.store(false, Ordering::Relaxed);
+        self.discard.store(false, Ordering::Relaxed);
+        let mut game = self.game.clone();
+        let out = self.out.clone();
+        let discard = self.discard.clone();
+        // the move and the result go out as soon as the search ends, not at the next command
+        self.worker = Some(thread::spawn(move || {
+            let result = searcher.run(&game);
+            let mv = match result.best_move {
+                Some(mv) => mv,
+                None => return (searcher, None),
+            };
+            let mut out = out.lock().expect("output lock poisoned");
+            if discard.load(Ordering::Relaxed) {
+                return (searcher, None);
+            }
+            game.make_move(&mv).expect("the search returned an illegal move");
+            writeln!(out, "move {}", mv.to_uci()).ok();
+            if let Some(result) = game_result(&game) {
+                writeln!(out, "{}", result).ok();
+            }
+            out.flush().ok();
+            (searcher, Some(game))
+        }));
+    }
+}
+
+pub fn run<R: BufRead, W: Write + Send + 'static>(input: R, output: W) {
+    let mut xboard = Xboard::new(Arc::new(Mutex::new(output)));
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if !xboard.handle_line(&line) {
+            return;
+        }
+    }
+    xboard.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(lines: &[&str]) -> String {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let mut xboard = Xboard::new(out.clone());
+        for line in lines {
+            xboard.handle_line(line);
+        }
+        xboard.wait();
+        let bytes = out.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn features_and_ping() {
+        let output = session(&["xboard", "protover 2", "ping 7"]);
+        assert!(output.starts_with("feature myname=\"antmag-chess\""));
+        assert!(output.contains("usermove=1"));
+        assert!(output.ends_with("pong 7\n"));
+    }
+
+    #[test]
+    fn replies_to_user_move() {
+        let output = session(&["new", "sd 2", "post", "usermove e2e4"]);
+        assert!(output.contains("\n2 "));
+        assert!(output.lines().last().unwrap().starts_with("move "));
+        assert_eq!(session(&["usermove e2e5"]), "Illegal move: e2e5\n");
+    }
+
+    #[test]
+    fn force_and_mate() {
+        let output = session(&["force", "setboard 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "sd 3", "go"]);
+        assert_eq!(output, "move a1a8\n1-0 {White mates}\n");
+        // force mode only records the moves
+        assert_eq!(session(&["force", "usermove e2e4", "usermove e7e5"]), "");
+    }
+
+    #[test]
+    fn result_without_another_command() {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let mut xboard = Xboard::new(out.clone());
+        for line in ["force", "setboard 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "sd 3", "go"] {
+            xboard.handle_line(line);
+        }
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while out.lock().unwrap().is_empty() && std::time::Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(String::from_utf8(out.lock().unwrap().clone()).unwrap(), "move a1a8\n1-0 {White mates}\n");
+        xboard.wait();
+    }
+
+    #[test]
+    fn force_throws_away_the_search() {
+        let started = std::time::Instant::now();
+        let output = session(&["new", "st 30", "go", "force", "usermove e2e4"]);
+        assert_eq!(output, "");
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn time_controls() {
+        assert_eq!(parse_level(&["40", "0:30", "0"]), Some((40, Duration::from_secs(30), Duration::ZERO)));
+        let tc = TimeControl { fixed_time: Some(Duration::from_secs(2)), ..Default::default() };
+        assert_eq!(tc.limits(&create_game()).movetime, Some(Duration::from_secs(2)));
+        let tc = TimeControl { moves_per_session: 40, time_left: Some(Duration::from_secs(40)), ..Default::default() };
+        assert_eq!(tc.limits(&create_game()).movetime, Some(Duration::from_secs(1)));
+    }
+}
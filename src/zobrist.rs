@@ -0,0 +1,54 @@
+use crate::piece::Definitions::*;
+
+const fn xorshift(mut x: u64) -> u64 {
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    x
+}
+
+const fn make_keys<const N: usize>(seed: u64) -> [u64; N] {
+    let mut keys = [0; N];
+    let mut x = seed;
+    let mut i = 0;
+    while i < N {
+        x = xorshift(x);
+        keys[i] = x;
+        i += 1;
+    }
+    keys
+}
+
+// 12 pieces times 64 squares
+const PIECE_KEYS: [u64; 768] = make_keys(0x9e37_79b9_7f4a_7c15);
+const CASTLE_KEYS: [u64; 4] = make_keys(0xc2b2_ae3d_27d4_eb4f);
+pub const SIDE_KEY: u64 = xorshift(0x1656_67b1_9e37_79f9);
+
+fn piece_index(p: Piece) -> usize {
+    let type_index = match p.piece_type {
+        PieceType::Pawn => 0,
+        PieceType::Knight => 1,
+        PieceType::Bishop => 2,
+        PieceType::Rook => 3,
+        PieceType::Queen => 4,
+        PieceType::King => 5,
+    };
+    match p.color {
+        Color::White => type_index,
+        Color::Black => type_index + 6,
+    }
+}
+
+pub fn piece_key(p: Piece, xy: (usize, usize)) -> u64 {
+    PIECE_KEYS[piece_index(p) * 64 + xy.0 + xy.1 * 8]
+}
+
+pub fn castle_key(rights: u8) -> u64 {
+    let mut key = 0;
+    for (i, castle_key) in CASTLE_KEYS.iter().enumerate() {
+        if rights & (1 << i) != 0 {
+            key ^= castle_key;
+        }
+    }
+    key
+}